@@ -9,10 +9,11 @@ use std::{
 type ShaderInfo = (&'static str, ShaderKind);
 
 const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders");
-const SHADERS: [ShaderInfo; 3] = [
+const SHADERS: [ShaderInfo; 4] = [
     ("interface/interface.frag", ShaderKind::Fragment),
     ("interface/interface.vert", ShaderKind::Vertex),
     ("raytracer.comp", ShaderKind::Compute),
+    ("tonemap.comp", ShaderKind::Compute),
 ];
 
 fn main() {
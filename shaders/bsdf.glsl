@@ -0,0 +1,179 @@
+// The glTF metallic-roughness BSDF, as described in Appendix B of the glTF 2.0 specification.
+// A Lambertian diffuse lobe and a GGX specular lobe are mixed by Fresnel-Schlick, and the metallic
+// factor blends between a dielectric (F0 of 0.04) and a conductor tinted by the base colour.
+// Source : https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#appendix-b-brdf-implementation
+//
+// All of the functions below work in the local shading frame given by calcONB, where the normal is
+// the y axis, matching the convention used by the sampling functions in random.glsl.
+
+// Calculates two perpendicular vectors, given a normal direction
+// n.
+// This implementation is based on Disney Pixar's "Building an Orthonormal Basis, Revisited",
+// where the code is referenced off listing 3 of the paper, found below.
+// Source : https://graphics.pixar.com/library/OrthonormalB/paper.pdf
+mat3 calcONB(vec3 n) {
+    float s = n.z >= 0.0 ? 1.0 : -1.0;
+    float a = -1.0 / (s + n.z);
+    float b = n.x * n.y * a;
+
+    // Store the vectors in a 3x3 matrix
+    mat3 onb;
+    onb[0] = vec3(1.0 + s * n.x * n.x * a, s * b, -s * n.x);
+    onb[1] = n;
+    onb[2] = vec3(b, s + n.y * n.y * a, -n.y);
+    return onb;
+}
+
+float luminance(vec3 color) {
+	return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+vec3 fresnelSchlick(vec3 f0, float cosTheta) {
+	return f0 + (1.0 - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+// Trowbridge-Reitz (GGX) normal distribution function
+float ggxDistribution(float NoH, float alpha) {
+	float a2 = alpha * alpha;
+	float d = NoH * NoH * (a2 - 1.0) + 1.0;
+	return a2 / (PI * d * d);
+}
+
+// Smith masking function for a single direction
+float smithG1(float NoX, float alpha) {
+	float a2 = alpha * alpha;
+	return 2.0 * NoX / (NoX + sqrt(a2 + (1.0 - a2) * NoX * NoX));
+}
+
+// Height-correlated Smith masking-shadowing function
+float smithG2(float NoV, float NoL, float alpha) {
+	float a2 = alpha * alpha;
+	float v = NoL * sqrt(a2 + (1.0 - a2) * NoV * NoV);
+	float l = NoV * sqrt(a2 + (1.0 - a2) * NoL * NoL);
+	return 2.0 * NoV * NoL / (v + l);
+}
+
+// Samples a microfacet normal from the distribution of normals visible from v.
+// Based on listing 1 of Heitz's "Sampling the GGX Distribution of Visible Normals", adapted to
+// the y-up shading frame.
+// Source : https://jcgt.org/published/0007/04/01/
+vec3 sampleGgxVndf(vec3 v, float alpha, vec2 r) {
+	// Stretch the view direction into the hemisphere configuration
+	vec3 vh = normalize(vec3(alpha * v.x, v.y, alpha * v.z));
+
+	// Build an orthonormal basis around it
+	float lenSq = vh.x * vh.x + vh.z * vh.z;
+	vec3 t1 = lenSq > 0.0 ? vec3(-vh.z, 0.0, vh.x) * inversesqrt(lenSq) : vec3(1.0, 0.0, 0.0);
+	vec3 t2 = cross(t1, vh);
+
+	// Sample a point on the projected disk
+	float radius = sqrt(r.x);
+	float phi = 2.0 * PI * r.y;
+	float p1 = radius * cos(phi);
+	float p2 = radius * sin(phi);
+	float s = 0.5 * (1.0 + vh.y);
+	p2 = (1.0 - s) * sqrt(1.0 - p1 * p1) + s * p2;
+
+	// Reproject onto the hemisphere, and unstretch back into the ellipsoid configuration
+	vec3 nh = p1 * t1 + p2 * t2 + sqrt(max(0.0, 1.0 - p1 * p1 - p2 * p2)) * vh;
+	return normalize(vec3(alpha * nh.x, max(0.0, nh.y), alpha * nh.z));
+}
+
+// The material properties the BSDF is evaluated with
+struct BsdfParams {
+	vec3 diffuse;
+	vec3 f0;
+	float alpha;
+};
+
+BsdfParams getBsdfParams(Material material) {
+	BsdfParams params;
+	params.diffuse = material.baseColor.rgb * (1.0 - material.metallic);
+	params.f0 = mix(vec3(0.04), material.baseColor.rgb, material.metallic);
+
+	// Perceptual roughness is squared, and clamped to keep perfectly smooth surfaces stable
+	float roughness = clamp(material.roughness, 0.03, 1.0);
+	params.alpha = roughness * roughness;
+	return params;
+}
+
+// Probability of sampling the specular lobe rather than the diffuse one, from the viewing angle
+float specularProbability(BsdfParams params, float NoV) {
+	vec3 fresnel = fresnelSchlick(params.f0, NoV);
+	float specular = luminance(fresnel);
+	float diffuse = luminance(params.diffuse * (1.0 - fresnel));
+	return clamp(specular / max(specular + diffuse, 0.0001), 0.1, 0.9);
+}
+
+// Returns the BSDF multiplied by the cosine term, for directions in the local shading frame
+vec3 evalBsdfLocal(BsdfParams params, vec3 v, vec3 l) {
+	if (v.y <= 0.0 || l.y <= 0.0) {
+		return vec3(0.0);
+	}
+
+	vec3 h = normalize(v + l);
+	float NoH = max(h.y, 0.0);
+	float VoH = max(dot(v, h), 0.0);
+
+	vec3 fresnel = fresnelSchlick(params.f0, VoH);
+	float d = ggxDistribution(NoH, params.alpha);
+	float g = smithG2(v.y, l.y, params.alpha);
+
+	vec3 specular = fresnel * d * g / (4.0 * v.y * l.y);
+	vec3 diffuse = (1.0 - fresnel) * params.diffuse / PI;
+	return (diffuse + specular) * l.y;
+}
+
+// Returns the solid angle pdf of sampleBsdf generating l, for directions in the local shading frame
+float pdfBsdfLocal(BsdfParams params, vec3 v, vec3 l) {
+	if (v.y <= 0.0 || l.y <= 0.0) {
+		return 0.0;
+	}
+
+	vec3 h = normalize(v + l);
+	float specularPdf = smithG1(v.y, params.alpha) * ggxDistribution(max(h.y, 0.0), params.alpha) / (4.0 * v.y);
+	float diffusePdf = l.y / PI;
+
+	float p = specularProbability(params, v.y);
+	return p * specularPdf + (1.0 - p) * diffusePdf;
+}
+
+vec3 evalBsdf(Material material, vec3 n, vec3 wo, vec3 wi) {
+	mat3 toLocal = transpose(calcONB(n));
+	return evalBsdfLocal(getBsdfParams(material), toLocal * wo, toLocal * wi);
+}
+
+float pdfBsdf(Material material, vec3 n, vec3 wo, vec3 wi) {
+	mat3 toLocal = transpose(calcONB(n));
+	return pdfBsdfLocal(getBsdfParams(material), toLocal * wo, toLocal * wi);
+}
+
+// Picks a lobe and samples an incoming direction from it, given the outgoing direction wo.
+// r is 3 uniformly random floats in the range [0, 1)
+// Returns false if no valid direction could be generated, in which case the path should end.
+bool sampleBsdf(Material material, vec3 n, vec3 wo, vec3 r, out BsdfSample bsdfSample) {
+	mat3 onb = calcONB(n);
+	vec3 v = transpose(onb) * wo;
+	if (v.y <= 0.0) {
+		return false;
+	}
+
+	BsdfParams params = getBsdfParams(material);
+
+	vec3 l;
+	if (r.z < specularProbability(params, v.y)) {
+		vec3 h = sampleGgxVndf(v, params.alpha, r.xy);
+		l = reflect(-v, h);
+	} else {
+		l = cosineSampleHemisphere(r.xy);
+	}
+
+	bsdfSample.pdf = pdfBsdfLocal(params, v, l);
+	if (bsdfSample.pdf <= 0.0) {
+		return false;
+	}
+
+	bsdfSample.dir = normalize(onb * l);
+	bsdfSample.weight = evalBsdfLocal(params, v, l) / bsdfSample.pdf;
+	return true;
+}
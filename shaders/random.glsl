@@ -21,4 +21,33 @@ vec3 uniformSampleHemisphere(vec2 r) {
     float s = sqrt(1 - r.x * r.x);
     float phi = 2 * PI * r.y;
     return vec3(s * cos(phi), r.x, s * sin(phi));
-}
\ No newline at end of file
+}
+
+// Generates a cosine weighted random direction on the unit hemisphere, by projecting
+// uniformly distributed points on the unit disk up onto it (Malley's method)
+// r is 2 uniformly random floats in the range (0, 1)
+// The PDF of this function is cos(theta) / PI
+vec3 cosineSampleHemisphere(vec2 r) {
+    float s = sqrt(r.x);
+    float phi = 2 * PI * r.y;
+    return vec3(s * cos(phi), sqrt(max(0.0, 1 - r.x)), s * sin(phi));
+}
+// Generates a uniformly distributed point on a lens aperture of radius 1. With 3 or more blades
+// the aperture is a regular polygon, split into triangles around its centre, one of which is
+// picked by r.z and sampled uniformly. Otherwise the aperture is a circle.
+// r is 3 uniformly random floats in the range (0, 1)
+vec2 sampleAperture(vec3 r, uint blades) {
+	if (blades < 3) {
+		float s = sqrt(r.x);
+		float phi = 2 * PI * r.y;
+		return vec2(s * cos(phi), s * sin(phi));
+	}
+
+	float edge = 2 * PI / float(blades);
+	float blade = min(floor(r.z * float(blades)), float(blades - 1));
+	vec2 a = vec2(cos(blade * edge), sin(blade * edge));
+	vec2 b = vec2(cos((blade + 1) * edge), sin((blade + 1) * edge));
+
+	float s = sqrt(r.x);
+	return s * mix(a, b, r.y);
+}
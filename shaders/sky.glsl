@@ -0,0 +1,36 @@
+// The Preetham analytic daylight model. The sky's brightness and colour in a direction follow the
+// Perez distribution, which is scaled to the zenith's values computed on the CPU for the sun's
+// position and the turbidity of the atmosphere.
+// Source : https://courses.cs.duke.edu/cps124/spring08/assign/07_papers/p91-preetham.pdf
+
+// Converts CIE XYZ into linear sRGB, with a D65 white point
+const mat3 XYZ_TO_SRGB = mat3(
+	3.2406, -0.9689, 0.0557,
+	-1.5372, 1.8758, -0.2040,
+	-0.4986, 0.0415, 1.0570
+);
+
+// Radiance of the sky in the direction dir, not including the sun's disk
+vec3 skyRadiance(SkyModel sky, vec3 dir) {
+	// The model is only defined above the horizon, so the horizon is extended downwards
+	float cosTheta = max(dir.y, 0.001);
+	float cosGamma = clamp(dot(dir, sky.sunDirection.xyz), -1.0, 1.0);
+	float gamma = acos(cosGamma);
+
+	vec3 perez = (1.0 + sky.perez[0].xyz * exp(sky.perez[1].xyz / cosTheta))
+		* (1.0 + sky.perez[2].xyz * exp(sky.perez[3].xyz * gamma) + sky.perez[4].xyz * cosGamma * cosGamma);
+
+	// Chromaticity in x and y, and luminance in z
+	vec3 xyY = sky.zenith.xyz * perez;
+	vec3 xyz = vec3(xyY.x, xyY.y, 1.0 - xyY.x - xyY.y) * (xyY.z / xyY.y);
+	return max(XYZ_TO_SRGB * xyz, vec3(0.0));
+}
+
+bool inSunDisk(SkyModel sky, vec3 dir) {
+	return dot(dir, sky.sunDirection.xyz) >= sky.sunDirection.w;
+}
+
+// Solid angle pdf of uniformly sampling a direction within the sun's disk
+float sunPdf(SkyModel sky) {
+	return 1.0 / (2.0 * PI * (1.0 - sky.sunDirection.w));
+}
@@ -1,3 +1,14 @@
+// The Preetham daylight model's parameters, see sky.glsl
+struct SkyModel {
+	// The direction towards the sun, and the cosine of its angular radius in w
+	vec4 sunDirection;
+	vec4 sunRadiance;
+	// The zenith's x and y chromaticity and luminance, divided by the Perez function there
+	vec4 zenith;
+	// The Perez distribution's A to E coefficients, for x, y and luminance in turn
+	vec4 perez[5];
+};
+
 struct Uniforms {
 	uint seed; 
 	uint samples;
@@ -9,10 +20,40 @@ struct Uniforms {
 	float exposure;
 	float time;
 
+	// Number of frames already in the accumulation image
+	uint frame;
+
+	// Emissive triangles available for next event estimation, and their summed power
+	uint lightCount;
+	float lightPower;
+
+	// Angle between the rays of neighbouring pixels, which is how quickly ray cones widen
+	float spreadAngle;
+
+	// Lights from KHR_lights_punctual, which are picked uniformly
+	uint punctualLightCount;
+
+	// Non-zero if rays leave the camera in parallel, from its near plane
+	uint orthographic;
+
+	// Scales the environment's radiance, and rotates it around the y axis in radians
+	float environmentIntensity;
+	float environmentRotation;
+	// Normalises the environment's importance sampling pdf, zero if it's black
+	float environmentIntegral;
+
+	// Non-zero if the procedural sky surrounds the scene in place of the environment map
+	uint sky;
+
+	// Number of blades shaping the aperture into a polygon, or zero for a circular one
+	uint apertureBlades;
+
 	vec4 pos;
 
 	mat4 inverseView;
 	mat4 inverseProj;
+
+	SkyModel skyModel;
 };
 
 struct Globals {
@@ -22,17 +63,102 @@ struct Globals {
 struct Ray {
 	vec3 origin;
 	vec3 dir;
+	// Width of the ray cone at the origin
+	float coneWidth;
 };
 
+// An index into the texture array, or -1 if the material doesn't use the texture, and the set
+// of texture coordinates to sample it with
+struct TextureRef {
+	int index;
+	uint texCoord;
+};
+
+// Matches the alphaMode field of Material
+#define ALPHA_OPAQUE 0
+#define ALPHA_MASK 1
+#define ALPHA_BLEND 2
+
 struct Material {
 	vec4 baseColor;
 	vec4 emissive;
 	float roughness;
 	float metallic;
+	float normalScale;
+	float occlusionStrength;
+
+	TextureRef baseColorTexture;
+	TextureRef metallicRoughnessTexture;
+	TextureRef normalTexture;
+	TextureRef emissiveTexture;
+	TextureRef occlusionTexture;
+
+	uint alphaMode;
+	// Masked surfaces are only hit where their alpha is at least this
+	float alphaCutoff;
+};
+
+struct Vertex {
+	vec4 normal;
+	// The sign in w gives the handedness of the bitangent
+	vec4 tangent;
+	// The first set of texture coordinates in xy, and the second in zw
+	vec4 texCoords;
+};
+
+struct EmissiveTriangle {
+	vec4 v0;
+	vec4 v1;
+	vec4 v2;
+	vec4 emissive;
+	// Probability of picking this triangle or any before it
+	float cdf;
+	// The geometry and triangle this was taken from
+	uint geometry;
+	uint primitive;
+};
+
+// Matches the kind field of PunctualLight
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+struct PunctualLight {
+	vec4 position;
+	vec4 direction;
+	// The colour already multiplied by the intensity
+	vec4 color;
+	uint kind;
+	// Distance at which the light's influence reaches zero, or zero if it's unlimited
+	float range;
+	// Maps the cosine of the angle from a spot light's axis onto its falloff
+	float angleScale;
+	float angleOffset;
+};
+
+struct BsdfSample {
+	vec3 dir;
+	// The BSDF times the cosine term, divided by the pdf
+	vec3 weight;
+	float pdf;
 };
 
 struct HitInfo {
 	vec3 pos;
+	// Geometric normal of the triangle that was hit
 	vec3 normal;
+	// Interpolated from the vertices, and used to shade the hit
+	vec3 shadingNormal;
+	vec4 tangent;
+	// The first set of texture coordinates in xy, and the second in zw
+	vec4 texCoords;
+	// Width of the ray cone at the hit, and its footprint in units of each set of texture coordinates
+	float coneWidth;
+	vec2 texFootprint;
+	// The amount of indirect light reaching the surface, from the occlusion texture
+	float occlusion;
+	// The geometry that was hit, offset by the custom index of its instance
+	int geometry;
+	// The material's factors multiplied by its textures
 	Material material;
 };
@@ -0,0 +1,784 @@
+use crate::loader::objects::{GpuInstance, GpuMesh};
+use glam::{Mat4, Vec2, Vec3A};
+
+// A two level bounding volume hierarchy for ray queries on the CPU.
+// Like the BLAS/TLAS split used on the GPU, each object's triangles get their own MeshBvh
+// in object space, and a top level hierarchy over the transformed instances sits above them.
+
+pub struct Ray {
+    pub origin: Vec3A,
+    pub dir: Vec3A,
+}
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3A,
+    pub max: Vec3A,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3A::splat(f32::INFINITY),
+        max: Vec3A::splat(f32::NEG_INFINITY),
+    };
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn grow(&mut self, point: Vec3A) {
+        self.min = self.min.min(point);
+        self.max = self.max.max(point);
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3A {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let extent = (self.max - self.min).max(Vec3A::ZERO);
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    pub fn transform(&self, transform: &Mat4) -> Aabb {
+        // The corners of an empty box are infinite, and would turn into NaNs
+        if self.is_empty() {
+            return Aabb::EMPTY;
+        }
+
+        let mut bounds = Aabb::EMPTY;
+        for corner in 0..8 {
+            let mask = glam::BVec3A::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0);
+            let point = Vec3A::select(mask, self.max, self.min);
+            bounds.grow(transform.transform_point3a(point));
+        }
+        bounds
+    }
+
+    // Slab test, returning the distance the ray enters the box if it hits within [t_min, t_max]
+    pub fn intersect(&self, ray: &Ray, inv_dir: Vec3A, t_min: f32, t_max: f32) -> Option<f32> {
+        let t0 = (self.min - ray.origin) * inv_dir;
+        let t1 = (self.max - ray.origin) * inv_dir;
+
+        let near = t0.min(t1).max_element().max(t_min);
+        let far = t0.max(t1).min_element().min(t_max);
+
+        (near <= far).then_some(near)
+    }
+}
+
+pub struct Triangle {
+    pub v0: Vec3A,
+    pub v1: Vec3A,
+    pub v2: Vec3A,
+}
+
+impl Triangle {
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::EMPTY;
+        bounds.grow(self.v0);
+        bounds.grow(self.v1);
+        bounds.grow(self.v2);
+        bounds
+    }
+
+    pub fn normal(&self) -> Vec3A {
+        (self.v1 - self.v0).cross(self.v2 - self.v0).normalize()
+    }
+
+    // Möller-Trumbore ray-triangle intersection, which is double sided like opaque ray queries.
+    // Returns the distance along the ray and the barycentrics of the hit
+    // https://www.graphics.cornell.edu/pubs/1997/MT97.pdf
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, Vec2)> {
+        let e1 = self.v1 - self.v0;
+        let e2 = self.v2 - self.v0;
+
+        let p = ray.dir.cross(e2);
+        // Only rays parallel to the triangle are rejected, as any fixed threshold would also
+        // reject valid hits on small triangles
+        let det = e1.dot(p);
+        if det == 0.0 {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let s = ray.origin - self.v0;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(e1);
+        let v = ray.dir.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = e2.dot(q) * inv_det;
+        (t > t_min && t < t_max).then_some((t, Vec2::new(u, v)))
+    }
+}
+
+// Interior nodes store the index of their first child (the second is directly after it),
+// while leaves store a range into the primitive list
+#[derive(Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    first: u32,
+    count: u32,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+// A hierarchy over some set of primitives, built with binned SAH
+struct Hierarchy {
+    nodes: Vec<Node>,
+    // Maps leaf ranges back to the original primitive indices
+    primitives: Vec<u32>,
+}
+
+struct Bin {
+    bounds: Aabb,
+    count: u32,
+}
+
+impl Hierarchy {
+    const BINS: usize = 16;
+    const MAX_LEAF_SIZE: u32 = 4;
+
+    // Relative costs of traversing a node and intersecting a primitive
+    const TRAVERSAL_COST: f32 = 1.0;
+    const INTERSECTION_COST: f32 = 1.0;
+
+    fn build(bounds: &[Aabb]) -> Self {
+        let centroids = bounds.iter().map(Aabb::centroid).collect::<Vec<Vec3A>>();
+
+        let mut hierarchy = Hierarchy {
+            nodes: Vec::with_capacity(bounds.len() * 2),
+            primitives: (0..bounds.len() as u32).collect(),
+        };
+
+        hierarchy.nodes.push(Node {
+            bounds: Aabb::EMPTY,
+            first: 0,
+            count: bounds.len() as u32,
+        });
+
+        if !bounds.is_empty() {
+            hierarchy.subdivide(0, bounds, &centroids);
+        }
+
+        hierarchy
+    }
+
+    fn subdivide(&mut self, index: usize, bounds: &[Aabb], centroids: &[Vec3A]) {
+        let node = self.nodes[index];
+        let range = node.first as usize..(node.first + node.count) as usize;
+
+        let mut node_bounds = Aabb::EMPTY;
+        let mut centroid_bounds = Aabb::EMPTY;
+        for &primitive in &self.primitives[range.clone()] {
+            node_bounds = node_bounds.union(&bounds[primitive as usize]);
+            centroid_bounds.grow(centroids[primitive as usize]);
+        }
+        self.nodes[index].bounds = node_bounds;
+
+        if node.count <= Self::MAX_LEAF_SIZE {
+            return;
+        }
+
+        let Some((axis, split)) = self.find_split(
+            range.clone(),
+            bounds,
+            centroids,
+            &node_bounds,
+            &centroid_bounds,
+        ) else {
+            return;
+        };
+
+        // Partition the primitives in place around the chosen split plane
+        let primitives = &mut self.primitives[range.clone()];
+        let mut left = 0;
+        for i in 0..primitives.len() {
+            if centroids[primitives[i] as usize][axis] < split {
+                primitives.swap(i, left);
+                left += 1;
+            }
+        }
+
+        if left == 0 || left == primitives.len() {
+            return;
+        }
+
+        let first_child = self.nodes.len();
+        self.nodes.push(Node {
+            bounds: Aabb::EMPTY,
+            first: node.first,
+            count: left as u32,
+        });
+        self.nodes.push(Node {
+            bounds: Aabb::EMPTY,
+            first: node.first + left as u32,
+            count: node.count - left as u32,
+        });
+
+        self.nodes[index].first = first_child as u32;
+        self.nodes[index].count = 0;
+
+        self.subdivide(first_child, bounds, centroids);
+        self.subdivide(first_child + 1, bounds, centroids);
+    }
+
+    // Evaluates the surface area heuristic at the boundaries between bins on each axis,
+    // returning the best split if it is cheaper than making a leaf
+    fn find_split(
+        &self,
+        range: std::ops::Range<usize>,
+        bounds: &[Aabb],
+        centroids: &[Vec3A],
+        node_bounds: &Aabb,
+        centroid_bounds: &Aabb,
+    ) -> Option<(usize, f32)> {
+        let count = range.len();
+        let leaf_cost = count as f32 * Self::INTERSECTION_COST;
+        let mut best: Option<(usize, f32, f32)> = None;
+
+        let mins = centroid_bounds.min.to_array();
+        let extents = (centroid_bounds.max - centroid_bounds.min).to_array();
+
+        for (axis, (min, extent)) in mins.into_iter().zip(extents).enumerate() {
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bins = std::array::from_fn::<Bin, { Self::BINS }, _>(|_| Bin {
+                bounds: Aabb::EMPTY,
+                count: 0,
+            });
+
+            let scale = Self::BINS as f32 / extent;
+            for &primitive in &self.primitives[range.clone()] {
+                let offset = (centroids[primitive as usize][axis] - min) * scale;
+                let bin = &mut bins[(offset as usize).min(Self::BINS - 1)];
+                bin.bounds = bin.bounds.union(&bounds[primitive as usize]);
+                bin.count += 1;
+            }
+
+            // Sweep from both sides to get the area and count on each side of every boundary
+            let mut left_areas = [0.0; Self::BINS - 1];
+            let mut left_counts = [0; Self::BINS - 1];
+            let mut right_areas = [0.0; Self::BINS - 1];
+            let mut right_counts = [0; Self::BINS - 1];
+
+            let mut left_bounds = Aabb::EMPTY;
+            let mut right_bounds = Aabb::EMPTY;
+            let mut left_count = 0;
+            let mut right_count = 0;
+            for i in 0..Self::BINS - 1 {
+                left_bounds = left_bounds.union(&bins[i].bounds);
+                left_count += bins[i].count;
+                left_areas[i] = left_bounds.surface_area();
+                left_counts[i] = left_count;
+
+                let j = Self::BINS - 1 - i;
+                right_bounds = right_bounds.union(&bins[j].bounds);
+                right_count += bins[j].count;
+                right_areas[j - 1] = right_bounds.surface_area();
+                right_counts[j - 1] = right_count;
+            }
+
+            for i in 0..Self::BINS - 1 {
+                if left_counts[i] == 0 || right_counts[i] == 0 {
+                    continue;
+                }
+
+                let cost = Self::TRAVERSAL_COST
+                    + Self::INTERSECTION_COST
+                        * (left_areas[i] * left_counts[i] as f32
+                            + right_areas[i] * right_counts[i] as f32)
+                        / node_bounds.surface_area();
+
+                if !best.is_some_and(|(_, _, best_cost)| cost >= best_cost) {
+                    let split = min + (i + 1) as f32 / scale;
+                    best = Some((axis, split, cost));
+                }
+            }
+        }
+
+        match best {
+            Some((axis, split, cost)) if cost < leaf_cost => Some((axis, split)),
+            _ => None,
+        }
+    }
+
+    // Visits leaves front to back, calling `hit` for each primitive in a leaf the ray reaches.
+    // `hit` returns the new closest distance if the primitive was hit
+    fn traverse(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        mut t_max: f32,
+        mut hit: impl FnMut(u32, f32) -> Option<f32>,
+    ) {
+        if self.primitives.is_empty() {
+            return;
+        }
+
+        let inv_dir = ray.dir.recip();
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0usize);
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.intersect(ray, inv_dir, t_min, t_max).is_none() {
+                continue;
+            }
+
+            if node.is_leaf() {
+                let range = node.first as usize..(node.first + node.count) as usize;
+                for &primitive in &self.primitives[range] {
+                    if let Some(t) = hit(primitive, t_max) {
+                        t_max = t;
+                    }
+                }
+                continue;
+            }
+
+            // Push the further child first, so the nearer one is visited first
+            let left = node.first as usize;
+            let right = left + 1;
+            let left_t = self.nodes[left]
+                .bounds
+                .intersect(ray, inv_dir, t_min, t_max);
+            let right_t = self.nodes[right]
+                .bounds
+                .intersect(ray, inv_dir, t_min, t_max);
+
+            match (left_t, right_t) {
+                (Some(l), Some(r)) if l <= r => stack.extend([right, left]),
+                (Some(_), Some(_)) => stack.extend([left, right]),
+                (Some(_), None) => stack.push(left),
+                (None, Some(_)) => stack.push(right),
+                (None, None) => (),
+            }
+        }
+    }
+}
+
+pub struct MeshHit {
+    pub t: f32,
+    pub geometry: u32,
+    pub primitive: u32,
+    pub barycentrics: Vec2,
+    // Geometric normal of the hit triangle, in object space
+    pub normal: Vec3A,
+}
+
+// The triangles of a single mesh, in object space
+pub struct MeshBvh {
+    triangles: Vec<Triangle>,
+    // The geometry each triangle belongs to, and its index within that geometry
+    ids: Vec<(u32, u32)>,
+    hierarchy: Hierarchy,
+}
+
+impl MeshBvh {
+    // Builds a single hierarchy over the vertices and indices of each of the mesh's geometries
+    pub fn new<'a>(geometries: impl IntoIterator<Item = (&'a [f32], &'a [u32])>) -> Self {
+        let mut triangles = Vec::new();
+        let mut ids = Vec::new();
+
+        for (geometry, (vertices, indices)) in geometries.into_iter().enumerate() {
+            let vertex = |index: u32| Vec3A::from_slice(&vertices[index as usize * 3..]);
+
+            for (primitive, i) in indices.chunks_exact(3).enumerate() {
+                triangles.push(Triangle {
+                    v0: vertex(i[0]),
+                    v1: vertex(i[1]),
+                    v2: vertex(i[2]),
+                });
+                ids.push((geometry as u32, primitive as u32));
+            }
+        }
+
+        let bounds = triangles
+            .iter()
+            .map(Triangle::bounds)
+            .collect::<Vec<Aabb>>();
+        let hierarchy = Hierarchy::build(&bounds);
+
+        Self {
+            triangles,
+            ids,
+            hierarchy,
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.hierarchy.nodes[0].bounds
+    }
+
+    // Finds the closest hit that `accept` returns true for
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        mut accept: impl FnMut(&MeshHit) -> bool,
+    ) -> Option<MeshHit> {
+        let mut closest = None;
+
+        self.hierarchy
+            .traverse(ray, t_min, t_max, |primitive, t_max| {
+                let triangle = &self.triangles[primitive as usize];
+                let (t, barycentrics) = triangle.intersect(ray, t_min, t_max)?;
+                let (geometry, primitive) = self.ids[primitive as usize];
+                let hit = MeshHit {
+                    t,
+                    geometry,
+                    primitive,
+                    barycentrics,
+                    normal: triangle.normal(),
+                };
+                if !accept(&hit) {
+                    return None;
+                }
+
+                closest = Some(hit);
+                Some(t)
+            });
+
+        closest
+    }
+}
+
+struct Instance {
+    // Position of the instance in the scene, which hits report
+    index: u32,
+    mesh: usize,
+    inv_transform: Mat4,
+    bounds: Aabb,
+}
+
+pub struct Hit {
+    pub t: f32,
+    pub instance: u32,
+    pub mesh: u32,
+    pub geometry: u32,
+    pub primitive: u32,
+    pub barycentrics: Vec2,
+    // Geometric normal of the hit triangle, in world space
+    pub normal: Vec3A,
+}
+
+pub struct SceneBvh {
+    meshes: Vec<MeshBvh>,
+    instances: Vec<Instance>,
+    hierarchy: Hierarchy,
+}
+
+impl SceneBvh {
+    // Builds one BVH for each mesh and places it at each of its instances, mirroring
+    // Scene::build_meshes and Scene::build_tlas
+    pub fn new(meshes: &[GpuMesh], instances: &[GpuInstance]) -> Self {
+        let meshes = meshes
+            .iter()
+            .map(|mesh| {
+                let primitives = mesh.primitives.iter();
+                MeshBvh::new(primitives.map(|p| (p.vertices.as_slice(), p.indices.as_slice())))
+            })
+            .collect::<Vec<MeshBvh>>();
+
+        // Instances of empty meshes can never be hit, and have no centroid to build around
+        let instances = instances
+            .iter()
+            .enumerate()
+            .map(|(index, instance)| Instance {
+                index: index as u32,
+                mesh: instance.mesh,
+                inv_transform: instance.transform.inverse(),
+                bounds: meshes[instance.mesh]
+                    .bounds()
+                    .transform(&instance.transform),
+            })
+            .filter(|instance| !instance.bounds.is_empty())
+            .collect::<Vec<Instance>>();
+
+        let bounds = instances.iter().map(|i| i.bounds).collect::<Vec<Aabb>>();
+        let hierarchy = Hierarchy::build(&bounds);
+
+        Self {
+            meshes,
+            instances,
+            hierarchy,
+        }
+    }
+
+    // Finds the closest hit, skipping any that `accept` returns false for, given the mesh and the
+    // hit in its object space. Like candidates in the shader, hits can be tested in any order
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        mut accept: impl FnMut(usize, &MeshHit) -> bool,
+    ) -> Option<Hit> {
+        let mut closest = None;
+
+        self.hierarchy.traverse(ray, t_min, t_max, |index, t_max| {
+            let instance = &self.instances[index as usize];
+            let mesh = &self.meshes[instance.mesh];
+
+            // Transform the ray into object space. The direction isn't renormalised,
+            // so distances along the ray stay the same in both spaces
+            let local = Ray {
+                origin: instance.inv_transform.transform_point3a(ray.origin),
+                dir: instance.inv_transform.transform_vector3a(ray.dir),
+            };
+
+            let hit = mesh.intersect(&local, t_min, t_max, |hit| accept(instance.mesh, hit))?;
+
+            let normal = (instance.inv_transform.transpose())
+                .transform_vector3a(hit.normal)
+                .normalize();
+
+            closest = Some(Hit {
+                t: hit.t,
+                instance: instance.index,
+                mesh: instance.mesh as u32,
+                geometry: hit.geometry,
+                primitive: hit.primitive,
+                barycentrics: hit.barycentrics,
+                normal,
+            });
+            Some(hit.t)
+        });
+
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::objects::GpuPrimitive;
+    use glam::{Quat, Vec3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const RAYS: usize = 2000;
+
+    fn random_point(rng: &mut StdRng, extent: f32) -> Vec3A {
+        Vec3A::new(
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+        )
+    }
+
+    // A primitive of unconnected triangles, each no larger than `size`, scattered around the origin
+    fn triangle_soup(rng: &mut StdRng, triangles: usize, size: f32) -> GpuPrimitive {
+        let mut vertices = Vec::with_capacity(triangles * 9);
+        for _ in 0..triangles {
+            let centre = random_point(rng, 1.0);
+            for _ in 0..3 {
+                vertices.extend((centre + random_point(rng, size)).to_array());
+            }
+        }
+
+        let count = triangles * 3;
+        GpuPrimitive {
+            vertices,
+            indices: (0..count as u32).collect(),
+            normals: vec![Vec3::Y; count],
+            tangents: vec![glam::Vec4::ZERO; count],
+            tex_coords: [vec![Vec2::ZERO; count], vec![Vec2::ZERO; count]],
+            material: 0,
+        }
+    }
+
+    // Rays from around the scene towards points near the origin, so most of them hit something
+    fn random_ray(rng: &mut StdRng) -> Ray {
+        let origin = random_point(rng, 3.0);
+        let target = random_point(rng, 1.0);
+        Ray {
+            origin,
+            dir: (target - origin).normalize(),
+        }
+    }
+
+    fn triangles(primitive: &GpuPrimitive) -> impl Iterator<Item = Triangle> + '_ {
+        let vertex = |index: u32| Vec3A::from_slice(&primitive.vertices[index as usize * 3..]);
+        primitive.indices.chunks_exact(3).map(move |i| Triangle {
+            v0: vertex(i[0]),
+            v1: vertex(i[1]),
+            v2: vertex(i[2]),
+        })
+    }
+
+    // The closest hit found by testing every triangle of every instance, as (t, instance,
+    // geometry, primitive)
+    fn brute_force(
+        meshes: &[GpuMesh],
+        instances: &[GpuInstance],
+        ray: &Ray,
+    ) -> Option<(f32, u32, u32, u32)> {
+        let mut closest: Option<(f32, u32, u32, u32)> = None;
+
+        for (index, instance) in instances.iter().enumerate() {
+            let inv_transform = instance.transform.inverse();
+            let local = Ray {
+                origin: inv_transform.transform_point3a(ray.origin),
+                dir: inv_transform.transform_vector3a(ray.dir),
+            };
+
+            let primitives = meshes[instance.mesh].primitives.iter();
+            for (geometry, primitive) in primitives.enumerate() {
+                for (triangle, candidate) in triangles(primitive).enumerate() {
+                    let t_max = closest.map_or(f32::INFINITY, |hit| hit.0);
+                    if let Some((t, _)) = candidate.intersect(&local, 0.0, t_max) {
+                        closest = Some((t, index as u32, geometry as u32, triangle as u32));
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+
+    fn check_scene(meshes: &[GpuMesh], instances: &[GpuInstance], rng: &mut StdRng) {
+        let bvh = SceneBvh::new(meshes, instances);
+
+        let mut hits = 0;
+        for _ in 0..RAYS {
+            let ray = random_ray(rng);
+            let expected = brute_force(meshes, instances, &ray);
+            let found = bvh
+                .intersect(&ray, 0.0, f32::INFINITY, |_, _| true)
+                .map(|hit| (hit.t, hit.instance, hit.geometry, hit.primitive));
+
+            match (found, expected) {
+                (Some(found), Some(expected)) => {
+                    assert!((found.0 - expected.0).abs() <= 1e-5 * expected.0.max(1.0));
+                    assert_eq!(
+                        (found.1, found.2, found.3),
+                        (expected.1, expected.2, expected.3)
+                    );
+                    hits += 1;
+                }
+                (None, None) => (),
+                _ => panic!("BVH found {:?}, brute force found {:?}", found, expected),
+            }
+        }
+
+        // Make sure the rays actually exercised the hierarchy
+        assert!(
+            hits > RAYS / 10,
+            "Only {} of {} rays hit anything",
+            hits,
+            RAYS
+        );
+    }
+
+    #[test]
+    fn mesh_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mesh = GpuMesh {
+            primitives: vec![
+                triangle_soup(&mut rng, 300, 0.2),
+                triangle_soup(&mut rng, 100, 0.5),
+            ],
+        };
+
+        let instance = GpuInstance {
+            mesh: 0,
+            transform: Mat4::IDENTITY,
+        };
+
+        check_scene(&[mesh], &[instance], &mut rng);
+    }
+
+    #[test]
+    fn instanced_meshes_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let meshes = (0..3)
+            .map(|_| GpuMesh {
+                primitives: vec![triangle_soup(&mut rng, 100, 0.3)],
+            })
+            .collect::<Vec<GpuMesh>>();
+
+        // Rotated, translated and non-uniformly scaled instances, several of each mesh
+        let instances = (0..12)
+            .map(|index| {
+                let axis = Vec3::from(random_point(&mut rng, 1.0)).normalize();
+                let rotation = Quat::from_axis_angle(axis, rng.gen_range(0.0..6.0));
+                let scale = Vec3::new(
+                    rng.gen_range(0.2..1.5),
+                    rng.gen_range(0.2..1.5),
+                    rng.gen_range(0.2..1.5),
+                );
+                let translation = Vec3::from(random_point(&mut rng, 1.5));
+
+                GpuInstance {
+                    mesh: index % meshes.len(),
+                    transform: Mat4::from_scale_rotation_translation(scale, rotation, translation),
+                }
+            })
+            .collect::<Vec<GpuInstance>>();
+
+        check_scene(&meshes, &instances, &mut rng);
+    }
+
+    #[test]
+    fn empty_meshes_are_never_hit() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let empty = GpuMesh {
+            primitives: vec![triangle_soup(&mut rng, 0, 0.0)],
+        };
+        let full = GpuMesh {
+            primitives: vec![triangle_soup(&mut rng, 200, 0.3)],
+        };
+
+        let transform = Mat4::from_rotation_y(1.0) * Mat4::from_scale(Vec3::splat(2.0));
+        assert!(Aabb::EMPTY.transform(&transform).is_empty());
+
+        // Empty instances around and between the others mustn't change which hits are found
+        let instances = [0, 1, 0, 1, 0].map(|mesh| GpuInstance { mesh, transform });
+        check_scene(&[empty, full], &instances, &mut rng);
+
+        let bvh = SceneBvh::new(&[GpuMesh { primitives: vec![] }], &instances[..1]);
+        assert!(bvh
+            .intersect(&random_ray(&mut rng), 0.0, f32::INFINITY, |_, _| true)
+            .is_none());
+    }
+
+    #[test]
+    fn small_triangles_are_hit() {
+        // A tenth of a millimetre across, as found in detailed scenes modelled in centimetres then
+        // scaled down, where the determinant is far smaller than f32::EPSILON
+        let triangle = Triangle {
+            v0: Vec3A::new(0.0, 0.0, 0.0),
+            v1: Vec3A::new(0.0001, 0.0, 0.0),
+            v2: Vec3A::new(0.0, 0.0001, 0.0),
+        };
+
+        let ray = Ray {
+            origin: Vec3A::new(0.00002, 0.00002, -1.0),
+            dir: Vec3A::Z,
+        };
+
+        let (t, _) = triangle.intersect(&ray, 0.0, f32::INFINITY).unwrap();
+        assert!((t - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    loader::{self, environment::Environment, SceneData},
+    render::{self, OfflineRenderer, ReferenceTracer},
+    world::{Tonemap, World},
+};
+use std::{path::PathBuf, str::FromStr};
+
+const USAGE: &str = "Usage: nea render <scene.gltf> [options]
+
+Options:
+    --scene <index>         Index of the glTF scene to render (default the file's default scene)
+    --camera <index>        Index of a camera in the scene to render from, in place of --fov
+    --environment <path>    Equirectangular .hdr or .exr environment map surrounding the scene
+    --width <pixels>        Width of the rendered image (default 1280)
+    --height <pixels>       Height of the rendered image (default 720)
+    --samples <count>       Samples per pixel traced each frame (default 8)
+    --bounces <count>       Bounces simulated per path (default 3)
+    --fov <degrees>         Vertical field of view (default 60)
+    --exposure <scale>      Exposure applied to the PNG output (default 1)
+    --tonemapper <name>     Tonemapper applied to the PNG output, reinhard, aces or agx (default aces)
+    --spp <count>           Total samples per pixel to render (default 256)
+    --output <path>         Output path, written as <path>.png and <path>.exr (default render)
+    --cpu                   Render with the CPU reference path tracer instead of the GPU";
+
+pub struct RenderArgs {
+    pub scene: PathBuf,
+    pub scene_index: Option<usize>,
+    pub camera: Option<usize>,
+    pub environment: Option<PathBuf>,
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub bounces: u32,
+    pub fov: f32,
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    pub spp: u32,
+    pub output: PathBuf,
+    pub cpu: bool,
+}
+
+impl RenderArgs {
+    pub fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut args = args.iter();
+
+        let Some(scene) = args.next() else {
+            anyhow::bail!("No scene given\n\n{}", USAGE)
+        };
+
+        let mut parsed = RenderArgs {
+            scene: PathBuf::from(scene),
+            scene_index: None,
+            camera: None,
+            environment: None,
+            width: 1280,
+            height: 720,
+            samples: 8,
+            bounces: 3,
+            fov: 60.0,
+            exposure: 1.0,
+            tonemap: Tonemap::default(),
+            spp: 256,
+            output: PathBuf::from("render"),
+            cpu: false,
+        };
+
+        while let Some(flag) = args.next() {
+            match flag.as_str() {
+                "--scene" => parsed.scene_index = Some(parse_value(flag, args.next())?),
+                "--camera" => parsed.camera = Some(parse_value(flag, args.next())?),
+                "--environment" => parsed.environment = Some(parse_value(flag, args.next())?),
+                "--width" => parsed.width = parse_value(flag, args.next())?,
+                "--height" => parsed.height = parse_value(flag, args.next())?,
+                "--samples" => parsed.samples = parse_value(flag, args.next())?,
+                "--bounces" => parsed.bounces = parse_value(flag, args.next())?,
+                "--fov" => parsed.fov = parse_value(flag, args.next())?,
+                "--exposure" => parsed.exposure = parse_value(flag, args.next())?,
+                "--tonemapper" => parsed.tonemap = parse_value(flag, args.next())?,
+                "--spp" => parsed.spp = parse_value(flag, args.next())?,
+                "--output" => parsed.output = parse_value(flag, args.next())?,
+                "--cpu" => parsed.cpu = true,
+                _ => anyhow::bail!("Unknown option {}\n\n{}", flag, USAGE),
+            }
+        }
+
+        if parsed.width == 0 || parsed.height == 0 {
+            anyhow::bail!("Image dimensions must be non-zero")
+        }
+
+        if parsed.samples == 0 {
+            anyhow::bail!("Sample count must be non-zero")
+        }
+
+        // With no samples to take, nothing would be rendered but the output would still be written
+        if parsed.spp == 0 {
+            anyhow::bail!("Total sample count must be non-zero")
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<&String>) -> anyhow::Result<T> {
+    let Some(value) = value else {
+        anyhow::bail!("Missing value for {}", flag)
+    };
+
+    match value.parse() {
+        Ok(value) => Ok(value),
+        Err(_) => anyhow::bail!("Invalid value for {} : {}", flag, value),
+    }
+}
+
+// Renders a scene to completion without opening a window, writing the result to disk
+pub fn run(args: &[String]) -> anyhow::Result<()> {
+    let args = RenderArgs::parse(args)?;
+
+    let mut world = World::new();
+    world.settings.samples = args.samples;
+    world.settings.bounces = args.bounces;
+    world.settings.fov = args.fov;
+    world.settings.exposure = args.exposure;
+    world.settings.tonemap = args.tonemap;
+
+    let mut scene = loader::load_scene(&args.scene, args.scene_index)?;
+
+    world.cameras = std::mem::take(&mut scene.cameras);
+    if let Some(index) = args.camera {
+        if index >= world.cameras.len() {
+            anyhow::bail!(
+                "No camera with index {}, the scene has {} cameras",
+                index,
+                world.cameras.len()
+            )
+        }
+
+        world.use_camera(index);
+    }
+
+    let environment = match &args.environment {
+        Some(path) => loader::environment::load_environment(path)?,
+        None => Environment::black(),
+    };
+
+    let radiance = if args.cpu {
+        render_cpu(&args, &world, scene, environment)
+    } else {
+        render_gpu(&args, &world, scene, environment)?
+    };
+
+    write_exr(&args, &radiance)?;
+    write_png(&args, &radiance)?;
+
+    Ok(())
+}
+
+fn render_gpu(
+    args: &RenderArgs,
+    world: &World,
+    scene: SceneData,
+    environment: Environment,
+) -> anyhow::Result<Vec<f32>> {
+    let mut renderer = OfflineRenderer::new(glam::uvec2(args.width, args.height));
+    renderer.load_scene(scene)?;
+    renderer.load_environment(&environment);
+
+    // Each frame traces `samples` paths per pixel, so keep rendering frames until the
+    // budget has been spent, the raytracer averages them as it goes
+    let passes = args.spp.div_ceil(args.samples);
+    for pass in 0..passes {
+        renderer.render(world);
+        log::info!("Rendered pass {}/{}", pass + 1, passes);
+    }
+
+    Ok(renderer.pixels())
+}
+
+fn render_cpu(
+    args: &RenderArgs,
+    world: &World,
+    scene: SceneData,
+    environment: Environment,
+) -> Vec<f32> {
+    let mut tracer = ReferenceTracer::new(&scene);
+    tracer.set_environment(environment);
+    let dims = glam::uvec2(args.width, args.height);
+
+    let passes = args.spp.div_ceil(args.samples);
+    let mut accumulated = vec![0.0f32; (args.width * args.height * 4) as usize];
+
+    for pass in 0..passes {
+        let pixels = tracer.render(world, dims);
+        for (sum, value) in accumulated.iter_mut().zip(pixels) {
+            *sum += value;
+        }
+
+        log::info!("Rendered pass {}/{} on the CPU", pass + 1, passes);
+    }
+
+    accumulated
+        .into_iter()
+        .map(|sum| sum / passes as f32)
+        .collect()
+}
+
+fn write_exr(args: &RenderArgs, radiance: &[f32]) -> anyhow::Result<()> {
+    let path = args.output.with_extension("exr");
+
+    let image = image::Rgba32FImage::from_raw(args.width, args.height, radiance.to_vec()).unwrap();
+    image.save(&path)?;
+
+    log::info!("Saved linear output to {}", path.display());
+    Ok(())
+}
+
+fn write_png(args: &RenderArgs, radiance: &[f32]) -> anyhow::Result<()> {
+    let path = args.output.with_extension("png");
+
+    let bytes = radiance
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let color = render::tonemap(glam::Vec3::from_slice(pixel), args.exposure, args.tonemap);
+            let encoded = color.to_array().map(|c| (c * 255.0).round() as u8);
+            [encoded[0], encoded[1], encoded[2], u8::MAX]
+        })
+        .collect::<Vec<u8>>();
+
+    let image = image::RgbaImage::from_raw(args.width, args.height, bytes).unwrap();
+    image.save(&path)?;
+
+    log::info!("Saved tonemapped output to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<RenderArgs> {
+        let args = args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<String>>();
+        RenderArgs::parse(&args)
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Ok(_) => panic!("{:?} should have been rejected", args),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let args = parse(&["scene.gltf"]).unwrap();
+
+        assert_eq!(args.scene, PathBuf::from("scene.gltf"));
+        assert_eq!(args.scene_index, None);
+        assert_eq!(args.camera, None);
+        assert_eq!(args.environment, None);
+        assert_eq!((args.width, args.height), (1280, 720));
+        assert_eq!(args.samples, 8);
+        assert_eq!(args.bounces, 3);
+        assert_eq!(args.fov, 60.0);
+        assert_eq!(args.exposure, 1.0);
+        assert_eq!(args.tonemap, Tonemap::default());
+        assert_eq!(args.spp, 256);
+        assert_eq!(args.output, PathBuf::from("render"));
+        assert!(!args.cpu);
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        let args = parse(&[
+            "scene.gltf",
+            "--scene",
+            "1",
+            "--camera",
+            "2",
+            "--environment",
+            "sky.hdr",
+            "--width",
+            "64",
+            "--height",
+            "32",
+            "--samples",
+            "4",
+            "--bounces",
+            "5",
+            "--fov",
+            "45",
+            "--exposure",
+            "2",
+            "--tonemapper",
+            "agx",
+            "--spp",
+            "16",
+            "--output",
+            "out/image",
+            "--cpu",
+        ])
+        .unwrap();
+
+        assert_eq!(args.scene_index, Some(1));
+        assert_eq!(args.camera, Some(2));
+        assert_eq!(args.environment, Some(PathBuf::from("sky.hdr")));
+        assert_eq!((args.width, args.height), (64, 32));
+        assert_eq!(args.samples, 4);
+        assert_eq!(args.bounces, 5);
+        assert_eq!(args.fov, 45.0);
+        assert_eq!(args.exposure, 2.0);
+        assert_eq!(args.tonemap, Tonemap::AgX);
+        assert_eq!(args.spp, 16);
+        assert_eq!(args.output, PathBuf::from("out/image"));
+        assert!(args.cpu);
+    }
+
+    #[test]
+    fn zero_values_are_rejected() {
+        assert!(error(&["scene.gltf", "--width", "0"]).contains("non-zero"));
+        assert!(error(&["scene.gltf", "--height", "0"]).contains("non-zero"));
+        assert!(error(&["scene.gltf", "--samples", "0"]).contains("non-zero"));
+        assert!(error(&["scene.gltf", "--spp", "0"]).contains("non-zero"));
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        assert!(error(&[]).starts_with("No scene given"));
+        assert_eq!(error(&["scene.gltf", "--spp"]), "Missing value for --spp");
+        assert_eq!(
+            error(&["scene.gltf", "--width", "wide"]),
+            "Invalid value for --width : wide"
+        );
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        assert!(error(&["scene.gltf", "--quality", "high"]).starts_with("Unknown option --quality"));
+    }
+}
@@ -1,26 +1,43 @@
 use crossbeam_channel::{Receiver, Sender};
+use std::collections::HashSet;
 use winit::{
     event::{DeviceEvent, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
 };
 
 pub enum Input {
-    Keyboard(KeyCode),
+    Keyboard {
+        key: KeyCode,
+        pressed: bool,
+    },
     Mouse(glam::DVec2),
+    // A left click on the window, in pixels from its top left corner
+    Click {
+        position: glam::DVec2,
+        size: glam::UVec2,
+    },
+    // The window stopped receiving input, so nothing can be held down anymore
+    FocusLost,
     Unknown,
 }
 
 impl Input {
     pub fn from_window_event(event: WindowEvent) -> Self {
         match event {
-            WindowEvent::KeyboardInput { event, .. } => {
-                if let PhysicalKey::Code(code) = event.physical_key {
-                    Input::Keyboard(code)
+            // Held keys are tracked by Inputs, so repeats carry no extra information
+            WindowEvent::KeyboardInput { event, .. } if !event.repeat => {
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    Input::Keyboard {
+                        key,
+                        pressed: event.state.is_pressed(),
+                    }
                 } else {
                     Input::Unknown
                 }
             }
 
+            WindowEvent::Focused(false) => Input::FocusLost,
+
             _ => Input::Unknown,
         }
     }
@@ -42,6 +59,7 @@ pub type InputBroadcaster = Sender<Input>;
 pub struct Inputs {
     pub broadcaster: InputBroadcaster,
     pub listener: InputListener,
+    held: HashSet<KeyCode>,
 }
 
 impl Inputs {
@@ -50,6 +68,34 @@ impl Inputs {
         Self {
             broadcaster,
             listener,
+            held: HashSet::new(),
         }
     }
+
+    // Takes every input received since the last call, keeping track of which keys are held down
+    pub fn drain(&mut self) -> Vec<Input> {
+        let inputs: Vec<Input> = self.listener.try_iter().collect();
+
+        for input in &inputs {
+            match input {
+                Input::Keyboard { key, pressed: true } => {
+                    self.held.insert(*key);
+                }
+                Input::Keyboard {
+                    key,
+                    pressed: false,
+                } => {
+                    self.held.remove(key);
+                }
+                Input::FocusLost => self.held.clear(),
+                _ => (),
+            }
+        }
+
+        inputs
+    }
+
+    pub fn is_held(&self, key: KeyCode) -> bool {
+        self.held.contains(&key)
+    }
 }
@@ -1,16 +1,22 @@
 use crate::{
     input::{Input, Inputs},
-    loader::SceneLoader,
-    world::World,
+    loader::{cameras::CameraKind, SceneLoader},
+    world::{CameraMode, Projection, Sky, Tonemap, World},
 };
 
-use winit::{event::WindowEvent, window::Window};
+use winit::{
+    event::{ElementState, MouseButton, WindowEvent},
+    window::Window,
+};
 
 pub struct Interface {
     interface_context: egui::Context,
     window_integration: egui_winit::State,
 
     last_output: egui::FullOutput,
+
+    // Last known position of the cursor over the window, in pixels
+    cursor: glam::DVec2,
 }
 
 impl Interface {
@@ -28,13 +34,42 @@ impl Interface {
             interface_context,
             window_integration,
             last_output: egui::FullOutput::default(),
+            cursor: glam::DVec2::ZERO,
         }
     }
 
     pub fn handle_event(&mut self, window: &Window, event: WindowEvent, inputs: &Inputs) {
         let response = self.window_integration.on_window_event(window, &event);
+
+        if let WindowEvent::CursorMoved { position, .. } = event {
+            self.cursor = glam::dvec2(position.x, position.y);
+        }
+
         if !response.consumed {
-            inputs.broadcaster.try_send(Input::from_window_event(event));
+            let input = match event {
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    let size = window.inner_size();
+                    Input::Click {
+                        position: self.cursor,
+                        size: glam::uvec2(size.width, size.height),
+                    }
+                }
+
+                event => Input::from_window_event(event),
+            };
+
+            inputs.broadcaster.try_send(input);
+        } else if let WindowEvent::KeyboardInput { .. } = event {
+            // Releases still need to reach the camera, otherwise a key let go of over the
+            // interface would stay held
+            let input = Input::from_window_event(event);
+            if let Input::Keyboard { pressed: false, .. } = input {
+                let _ = inputs.broadcaster.try_send(input);
+            }
         }
     }
 
@@ -44,6 +79,7 @@ impl Interface {
 
         self.scene_ui();
         self.camera_ui(world);
+        self.sky_ui(&mut world.settings.sky);
 
         let output = self.interface_context.end_frame();
         self.window_integration
@@ -62,16 +98,133 @@ impl Interface {
 
     pub fn camera_ui(&mut self, world: &mut World) {
         egui::Window::new("Camera").show(&self.context(), |ui| {
-            // TODO: ===================== REPLACE BELOW WITH ACTUAL CAMERA INPUTS =========================
+            if !world.cameras.is_empty() {
+                ui.label("Scene cameras: ");
+
+                let mut selected = None;
+                for (index, camera) in world.cameras.iter().enumerate() {
+                    let kind = match camera.kind {
+                        CameraKind::Perspective { .. } => "perspective",
+                        CameraKind::Orthographic { .. } => "orthographic",
+                    };
 
-            // TODO: ===================== REPLACE AVOVE WITH ACTUAL CAMERA INPUTS =========================
+                    if ui.button(format!("{} ({})", camera.name, kind)).clicked() {
+                        selected = Some(index);
+                    }
+                }
+
+                if let Some(index) = selected {
+                    world.use_camera(index);
+                }
+
+                ui.separator();
+            }
 
             egui::Grid::new("Camera UI")
                 .striped(true)
                 .num_columns(2)
                 .show(ui, |ui| {
-                    ui.label("Y FOV: ");
-                    ui.add(egui::DragValue::new(&mut world.settings.fov));
+                    ui.label("Projection: ");
+                    ui.horizontal(|ui| {
+                        let orthographic =
+                            matches!(world.settings.projection, Projection::Orthographic { .. });
+
+                        if ui.selectable_label(!orthographic, "Perspective").clicked() {
+                            world.settings.projection = Projection::Perspective;
+                        }
+
+                        if ui.selectable_label(orthographic, "Orthographic").clicked()
+                            && !orthographic
+                        {
+                            world.settings.projection = Projection::Orthographic { height: 4.0 };
+                        }
+                    });
+                    ui.end_row();
+
+                    match &mut world.settings.projection {
+                        Projection::Perspective => {
+                            ui.label("Y FOV: ");
+                            ui.add(egui::DragValue::new(&mut world.settings.fov));
+                        }
+                        Projection::Orthographic { height } => {
+                            ui.label("View height: ");
+                            ui.add(egui::DragValue::new(height).speed(0.01));
+                        }
+                    }
+                    ui.end_row();
+
+                    ui.label("Focus distance: ");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut world.settings.focal_length)
+                                .speed(0.01)
+                                .clamp_range(0.0..=f32::MAX),
+                        );
+
+                        // The next click on the scene picks the focus distance
+                        let text = if world.picking_focus {
+                            "Click the scene.."
+                        } else {
+                            "Pick"
+                        };
+                        if ui.button(text).clicked() {
+                            world.picking_focus = !world.picking_focus;
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Aperture: ");
+                    ui.add(
+                        egui::DragValue::new(&mut world.settings.aperture)
+                            .speed(0.001)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                    ui.end_row();
+
+                    ui.label("Aperture blades: ");
+                    ui.add(
+                        egui::DragValue::new(&mut world.settings.aperture_blades)
+                            .clamp_range(0..=16)
+                            .custom_formatter(|blades, _| match blades as u32 {
+                                0..=2 => "Circular".to_string(),
+                                blades => blades.to_string(),
+                            }),
+                    );
+                    ui.end_row();
+
+                    ui.label("Controls: ");
+                    ui.horizontal(|ui| {
+                        for mode in CameraMode::ALL {
+                            let selected = world.controls.mode == mode;
+                            if ui.selectable_label(selected, mode.name()).clicked() {
+                                world.set_camera_mode(mode);
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Movement speed: ");
+                    ui.add(
+                        egui::DragValue::new(&mut world.controls.speed)
+                            .speed(0.05)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                    ui.end_row();
+
+                    ui.label("Acceleration: ");
+                    ui.add(
+                        egui::DragValue::new(&mut world.controls.acceleration)
+                            .speed(0.1)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                    ui.end_row();
+
+                    ui.label("Mouse sensitivity: ");
+                    ui.add(
+                        egui::DragValue::new(&mut world.controls.sensitivity)
+                            .speed(0.0001)
+                            .clamp_range(0.0..=0.1),
+                    );
                     ui.end_row();
 
                     ui.label("Sample count: ");
@@ -81,6 +234,69 @@ impl Interface {
                     ui.label("Bounces simulated: ");
                     ui.add(egui::DragValue::new(&mut world.settings.bounces));
                     ui.end_row();
+
+                    ui.label("Exposure: ");
+                    ui.add(egui::DragValue::new(&mut world.settings.exposure).speed(0.01));
+                    ui.end_row();
+
+                    ui.label("Tonemapper: ");
+                    egui::ComboBox::from_id_source("Tonemapper")
+                        .selected_text(world.settings.tonemap.name())
+                        .show_ui(ui, |ui| {
+                            for tonemap in Tonemap::ALL {
+                                ui.selectable_value(
+                                    &mut world.settings.tonemap,
+                                    tonemap,
+                                    tonemap.name(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Environment intensity: ");
+                    ui.add(
+                        egui::DragValue::new(&mut world.settings.environment_intensity)
+                            .speed(0.01)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                    ui.end_row();
+
+                    ui.label("Environment rotation: ");
+                    ui.add(
+                        egui::Slider::new(&mut world.settings.environment_rotation, 0.0..=360.0)
+                            .suffix("°"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Samples accumulated: ");
+                    ui.label(format!(
+                        "{} ({} frames)",
+                        world.stats.samples, world.stats.frames
+                    ));
+                    ui.end_row();
+                });
+        });
+    }
+
+    pub fn sky_ui(&mut self, sky: &mut Sky) {
+        egui::Window::new("Sky").show(self.context(), |ui| {
+            ui.checkbox(&mut sky.enabled, "Use procedural sky");
+
+            egui::Grid::new("Sky UI")
+                .striped(true)
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Sun elevation: ");
+                    ui.add(egui::Slider::new(&mut sky.elevation, -10.0..=90.0).suffix("°"));
+                    ui.end_row();
+
+                    ui.label("Sun azimuth: ");
+                    ui.add(egui::Slider::new(&mut sky.azimuth, 0.0..=360.0).suffix("°"));
+                    ui.end_row();
+
+                    ui.label("Turbidity: ");
+                    ui.add(egui::Slider::new(&mut sky.turbidity, 2.0..=10.0));
+                    ui.end_row();
                 });
         });
     }
@@ -90,6 +306,10 @@ impl Interface {
             if ui.button("Load Scene").clicked() {
                 SceneLoader::request_load();
             }
+
+            if ui.button("Load Environment").clicked() {
+                SceneLoader::request_environment_load();
+            }
         });
     }
 }
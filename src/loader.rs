@@ -1,28 +1,48 @@
-use self::{images::GpuImage, objects::GpuObject};
+use self::{
+    cameras::SceneCamera,
+    environment::Environment,
+    images::{GpuImage, GpuTexture, TextureSet},
+    lights::GpuLight,
+    objects::{GpuInstance, GpuMaterial, GpuMesh, SceneObjects},
+};
 use parking_lot::Mutex;
 use std::{
+    path::Path,
     sync::OnceLock,
     thread::{self, JoinHandle},
 };
 
+pub mod cameras;
+pub mod environment;
 pub mod images;
+pub mod lights;
 pub mod objects;
 
 static GLOBAL_SCENE_LOADER: OnceLock<Mutex<SceneLoader>> = OnceLock::new();
 
 pub struct SceneData {
     pub images: Vec<GpuImage>,
-    pub objects: Vec<GpuObject>,
+    pub textures: Vec<GpuTexture>,
+    pub meshes: Vec<GpuMesh>,
+    pub instances: Vec<GpuInstance>,
+    pub materials: Vec<GpuMaterial>,
+    pub lights: Vec<GpuLight>,
+    pub cameras: Vec<SceneCamera>,
 }
 
 pub struct SceneLoader {
     load_thread: Option<JoinHandle<anyhow::Result<SceneData>>>,
+    environment_thread: Option<JoinHandle<anyhow::Result<Environment>>>,
 }
 
 impl SceneLoader {
     const fn new() -> Self {
         let load_thread = None;
-        SceneLoader { load_thread }
+        let environment_thread = None;
+        SceneLoader {
+            load_thread,
+            environment_thread,
+        }
     }
 
     pub fn init() {
@@ -69,6 +89,64 @@ impl SceneLoader {
 
         None
     }
+
+    pub fn request_environment_load() {
+        let mut asset_server = GLOBAL_SCENE_LOADER
+            .get_or_init(|| Mutex::new(SceneLoader::new()))
+            .lock();
+
+        if std::mem::take(&mut asset_server.environment_thread).is_some() {
+            log::info!(
+                "There is already an environment loading in the background, which will be cancelled"
+            );
+        }
+
+        let handle = thread::spawn(environment_load_task);
+        asset_server.environment_thread = Some(handle)
+    }
+
+    pub fn poll_environment() -> Option<Environment> {
+        let mut asset_server = GLOBAL_SCENE_LOADER
+            .get_or_init(|| Mutex::new(SceneLoader::new()))
+            .lock();
+
+        if let Some(join_handle) = &asset_server.environment_thread {
+            if join_handle.is_finished() {
+                let handle = std::mem::take(&mut asset_server.environment_thread).unwrap();
+
+                match handle.join().unwrap() {
+                    Ok(environment) => return Some(environment),
+                    Err(err) => log::error!("Failed to load environment : {}", err),
+                }
+            }
+        }
+
+        None
+    }
+}
+
+// Walks the node hierarchy of a scene depth first, calling `visit` with every node and its
+// world transform, composed from the transforms of all of its parents
+pub fn visit_nodes<'a>(
+    scene: &gltf::Scene<'a>,
+    mut visit: impl FnMut(&gltf::Node<'a>, glam::Mat4),
+) {
+    fn visit_node<'a>(
+        node: gltf::Node<'a>,
+        parent: glam::Mat4,
+        visit: &mut impl FnMut(&gltf::Node<'a>, glam::Mat4),
+    ) {
+        let transform = parent * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+        visit(&node, transform);
+
+        for child in node.children() {
+            visit_node(child, transform, visit);
+        }
+    }
+
+    for node in scene.nodes() {
+        visit_node(node, glam::Mat4::IDENTITY, &mut visit);
+    }
 }
 
 fn load_task() -> anyhow::Result<SceneData> {
@@ -77,20 +155,72 @@ fn load_task() -> anyhow::Result<SceneData> {
         anyhow::bail!("Scene load cancelled")
     };
 
+    load_scene(&file, None)
+}
+
+fn environment_load_task() -> anyhow::Result<Environment> {
+    let file_request = rfd::FileDialog::new()
+        .add_filter("Environment map", &["hdr", "exr"])
+        .pick_file();
+    let Some(file) = file_request else {
+        anyhow::bail!("Environment load cancelled")
+    };
+
+    environment::load_environment(&file)
+}
+
+// Loads the scene at `scene` in the file, or the file's default scene if no index is given
+pub fn load_scene(file: &Path, scene: Option<usize>) -> anyhow::Result<SceneData> {
     log::info!("Loading file..");
-    let (document, buffers, images) = gltf::import(&file)?;
+    let (document, buffers, images) = gltf::import(file)?;
+
+    let scene = match scene {
+        Some(index) => match document.scenes().nth(index) {
+            Some(scene) => scene,
+            None => anyhow::bail!(
+                "No scene with index {}, the file has {} scenes",
+                index,
+                document.scenes().len()
+            ),
+        },
+
+        // Files aren't required to name a default scene, in which case use the first one
+        None => {
+            let scene = document
+                .default_scene()
+                .or_else(|| document.scenes().next());
+            match scene {
+                Some(scene) => scene,
+                None => anyhow::bail!("The file doesn't contain any scenes"),
+            }
+        }
+    };
 
-    let mut gpu_images = Vec::with_capacity(images.len());
-    // Parse the images into a GPU-friendly format
-    for image in images {
-        let gpu_image = images::parse_image(image)?;
-        gpu_images.push(gpu_image)
-    }
+    log::info!(
+        "Loading scene {} ({})",
+        scene.index(),
+        scene.name().unwrap_or("unnamed")
+    );
+
+    // Only the images used by the scene's materials are loaded, in the colour spaces they need
+    let mut textures = TextureSet::default();
+    let SceneObjects {
+        meshes,
+        instances,
+        materials,
+    } = objects::load_meshes(&document, &scene, &buffers, &mut textures)?;
+    let (images, textures) = textures.load(&document, images)?;
 
-    let objects = objects::load_objects(&document, &buffers);
+    let lights = lights::load_lights(&scene);
+    let cameras = cameras::load_cameras(&scene);
 
     Ok(SceneData {
-        images: gpu_images,
-        objects,
+        images,
+        textures,
+        meshes,
+        instances,
+        materials,
+        lights,
+        cameras,
     })
 }
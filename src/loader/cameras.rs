@@ -0,0 +1,74 @@
+use super::visit_nodes;
+use gltf::camera::Projection;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CameraKind {
+    // Vertical field of view in radians
+    Perspective { yfov: f32 },
+    // Half of the vertical extent of the view, in world units
+    Orthographic { ymag: f32 },
+}
+
+// A camera authored in the glTF file, placed in world space
+#[derive(Clone)]
+pub struct SceneCamera {
+    pub name: String,
+    pub position: glam::Vec3A,
+    // Rotates the renderer's +z forward axis onto the direction the camera looks in
+    pub rotation: glam::Quat,
+
+    pub kind: CameraKind,
+    pub znear: f32,
+    // Perspective cameras may have an infinite far plane
+    pub zfar: Option<f32>,
+}
+
+pub fn load_cameras(scene: &gltf::Scene) -> Vec<SceneCamera> {
+    let mut cameras = Vec::new();
+
+    visit_nodes(scene, |node, transform| {
+        let Some(camera) = node.camera() else {
+            return;
+        };
+
+        // The aspect ratio is ignored, the image always keeps the proportions of the output
+        let (kind, znear, zfar) = match camera.projection() {
+            Projection::Perspective(perspective) => (
+                CameraKind::Perspective {
+                    yfov: perspective.yfov(),
+                },
+                perspective.znear(),
+                perspective.zfar(),
+            ),
+            Projection::Orthographic(orthographic) => (
+                CameraKind::Orthographic {
+                    ymag: orthographic.ymag(),
+                },
+                orthographic.znear(),
+                Some(orthographic.zfar()),
+            ),
+        };
+
+        // glTF cameras look down their local -z axis, whereas the renderer's look down +z
+        let (_, rotation, position) = transform.to_scale_rotation_translation();
+        let rotation = rotation.normalize() * glam::Quat::from_rotation_y(std::f32::consts::PI);
+
+        let name = match camera.name() {
+            Some(name) => name.to_string(),
+            None => format!("Camera {}", cameras.len()),
+        };
+
+        cameras.push(SceneCamera {
+            name,
+            position: position.into(),
+            rotation,
+            kind,
+            znear,
+            zfar,
+        });
+    });
+
+    log::info!("Found {} cameras", cameras.len());
+
+    cameras
+}
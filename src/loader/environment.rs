@@ -0,0 +1,117 @@
+use std::{f32::consts::PI, path::Path};
+
+// An equirectangular environment map surrounding the scene, along with the distribution used to
+// importance sample it
+pub struct Environment {
+    pub dims: glam::UVec2,
+    // Linear RGBA radiance, row by row from the top of the image
+    pub pixels: Vec<f32>,
+
+    // The marginal CDF over rows, followed by the conditional CDF over columns of each row
+    pub cdf: Vec<f32>,
+    // Mean of every texel's luminance times the sine of its polar angle, zero if it's black
+    pub integral: f32,
+}
+
+impl Environment {
+    // A black environment, for when there is nothing surrounding the scene
+    pub fn black() -> Self {
+        Self {
+            dims: glam::uvec2(1, 1),
+            pixels: vec![0.0, 0.0, 0.0, 1.0],
+            cdf: vec![1.0, 1.0],
+            integral: 0.0,
+        }
+    }
+
+    // Wraps an image of linear RGBA radiance, building the distribution to sample it with
+    pub fn new(dims: glam::UVec2, pixels: Vec<f32>) -> Self {
+        let mut environment = Self {
+            dims,
+            pixels,
+            cdf: Vec::new(),
+            integral: 0.0,
+        };
+
+        environment.build_distribution();
+        environment
+    }
+
+    pub fn texel(&self, x: u32, y: u32) -> glam::Vec3A {
+        let offset = (y * self.dims.x + x) as usize * 4;
+        glam::Vec3A::from_slice(&self.pixels[offset..offset + 3])
+    }
+
+    // Bilinearly filters the image at `uv`, like the renderer's sampler, which wraps around
+    // horizontally and clamps at the poles
+    pub fn sample(&self, uv: glam::Vec2) -> glam::Vec3A {
+        let position = uv * self.dims.as_vec2() - 0.5;
+        let base = position.floor();
+        let t = position - base;
+        let base = base.as_ivec2();
+
+        let texel = |dx: i32, dy: i32| {
+            let x = (base.x + dx).rem_euclid(self.dims.x as i32) as u32;
+            let y = (base.y + dy).clamp(0, self.dims.y as i32 - 1) as u32;
+            self.texel(x, y)
+        };
+
+        let top = texel(0, 0).lerp(texel(1, 0), t.x);
+        let bottom = texel(0, 1).lerp(texel(1, 1), t.x);
+        top.lerp(bottom, t.y)
+    }
+
+    // Builds a piecewise constant 2D distribution over the image, proportional to luminance.
+    // Each texel is weighted by sin(theta), as rows near the poles cover less solid angle.
+    // See section 13.6.5 of Physically Based Rendering (3rd edition).
+    // Source : https://pbr-book.org/3ed-2018/Monte_Carlo_Integration/2D_Sampling_with_Multidimensional_Transformations
+    fn build_distribution(&mut self) {
+        let [width, height] = self.dims.to_array().map(|x| x as usize);
+
+        let mut marginal = Vec::with_capacity(height);
+        let mut conditional = Vec::with_capacity(width * height);
+        let mut total = 0.0;
+
+        for y in 0..height {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+
+            let row_start = conditional.len();
+            let mut row_total = 0.0;
+            for x in 0..width {
+                let luminance = self
+                    .texel(x as u32, y as u32)
+                    .dot(glam::vec3a(0.2126, 0.7152, 0.0722));
+                row_total += luminance.max(0.0) * sin_theta;
+                conditional.push(row_total);
+            }
+
+            // Rows with nothing in them are never picked, but still need a valid CDF
+            for (x, cdf) in conditional[row_start..].iter_mut().enumerate() {
+                *cdf = if row_total > 0.0 {
+                    *cdf / row_total
+                } else {
+                    (x + 1) as f32 / width as f32
+                };
+            }
+
+            total += row_total;
+            marginal.push(total);
+        }
+
+        for cdf in &mut marginal {
+            *cdf = if total > 0.0 { *cdf / total } else { 1.0 };
+        }
+
+        marginal.extend(conditional);
+        self.cdf = marginal;
+        self.integral = total / (width * height) as f32;
+    }
+}
+
+pub fn load_environment(path: &Path) -> anyhow::Result<Environment> {
+    log::info!("Loading environment {}", path.display());
+    let image = image::open(path)?.into_rgba32f();
+    let dims = glam::uvec2(image.width(), image.height());
+
+    Ok(Environment::new(dims, image.into_raw()))
+}
@@ -1,24 +1,155 @@
 use ash::vk;
-use gltf::image::Format;
+use gltf::{
+    image::Format,
+    texture::{MagFilter, MinFilter, WrappingMode},
+};
 use image::Pixel;
+use std::collections::HashMap;
 use thiserror::Error;
 
 pub struct GpuImage {
     pub bytes: Vec<u8>,
     pub dims: glam::UVec3,
     pub format: vk::Format,
+
+    // Byte offset of each level of the mip chain, the first being the full resolution image
+    pub mip_offsets: Vec<usize>,
+}
+
+impl GpuImage {
+    // Size of a level of the mip chain
+    pub fn level_dims(&self, level: usize) -> glam::UVec2 {
+        (self.dims.truncate() >> level as u32).max(glam::UVec2::ONE)
+    }
+
+    // Decodes a level of the mip chain into linear RGBA values, for sampling on the CPU
+    pub fn texels(&self, level: usize) -> Vec<glam::Vec4> {
+        let (size, channels, srgb) = texel_layout(self.format);
+        let dims = self.level_dims(level);
+        let count = (dims.x * dims.y) as usize;
+
+        self.bytes[self.mip_offsets[level]..]
+            .chunks_exact(size * channels)
+            .take(count)
+            .map(|texel| {
+                let mut value = glam::Vec4::W;
+                for (channel, bytes) in texel.chunks_exact(size).enumerate() {
+                    value[channel] = decode_channel(bytes, srgb && channel < 3);
+                }
+                value
+            })
+            .collect()
+    }
+}
+
+// How the values in an image are encoded. glTF stores colours in sRGB, and everything else linearly
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+// A glTF texture, which pairs an image with the sampler state it's read with
+pub struct GpuTexture {
+    pub image: usize,
+    pub address_modes: [vk::SamplerAddressMode; 2],
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
 }
 
 #[derive(Error, Debug)]
 pub enum ImageLoadError {
-    #[error("Unsupported image format")]
-    UnsupportedFormat(gltf::image::Format),
     #[error("Failed to convert data")]
     DataConversionFailed(bytemuck::PodCastError),
 }
 
-pub fn parse_image(data: gltf::image::Data) -> Result<GpuImage, ImageLoadError> {
+// Collects the textures used by materials as they're loaded. Each texture is loaded once for
+// every colour space it's used with, as the same image can't be read as both sRGB and linear data
+#[derive(Default)]
+pub struct TextureSet {
+    indices: HashMap<(usize, ColorSpace), usize>,
+    textures: Vec<(usize, ColorSpace)>,
+}
+
+impl TextureSet {
+    // Returns the index the texture will have in the loaded scene
+    pub fn insert(&mut self, texture: gltf::Texture, color_space: ColorSpace) -> usize {
+        let key = (texture.index(), color_space);
+        *self.indices.entry(key).or_insert_with(|| {
+            self.textures.push(key);
+            self.textures.len() - 1
+        })
+    }
+
+    // Parses the images behind every texture in the set, skipping images which aren't used
+    pub fn load(
+        self,
+        document: &gltf::Document,
+        images: Vec<gltf::image::Data>,
+    ) -> Result<(Vec<GpuImage>, Vec<GpuTexture>), ImageLoadError> {
+        let mut image_indices = HashMap::new();
+        let mut gpu_images = Vec::new();
+        let mut gpu_textures = Vec::with_capacity(self.textures.len());
+
+        for (texture, color_space) in self.textures {
+            let texture = document.textures().nth(texture).unwrap();
+            let key = (texture.source().index(), color_space);
+
+            let image = match image_indices.get(&key) {
+                Some(&image) => image,
+                None => {
+                    gpu_images.push(parse_image(images[key.0].clone(), color_space)?);
+                    image_indices.insert(key, gpu_images.len() - 1);
+                    gpu_images.len() - 1
+                }
+            };
+
+            gpu_textures.push(parse_texture(texture, image));
+        }
+
+        Ok((gpu_images, gpu_textures))
+    }
+}
+
+pub fn parse_image(
+    data: gltf::image::Data,
+    color_space: ColorSpace,
+) -> Result<GpuImage, ImageLoadError> {
     let bytes = match data.format {
+        // Greyscale images are expanded to RGBA, so they sample as grey rather than red and keep
+        // their alpha in the alpha channel
+        Format::R8 => data
+            .pixels
+            .iter()
+            .flat_map(|&l| [l, l, l, u8::MAX])
+            .collect(),
+        Format::R8G8 => data
+            .pixels
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        Format::R16 | Format::R16G16 => {
+            let halfs = match bytemuck::try_cast_slice::<u8, u16>(&data.pixels) {
+                Ok(bytes) => bytes,
+                Err(err) => return Err(ImageLoadError::DataConversionFailed(err)),
+            };
+
+            let channels = if data.format == Format::R16 { 1 } else { 2 };
+            let expanded = halfs
+                .chunks_exact(channels)
+                .flat_map(|pixel| {
+                    let alpha = pixel.get(1).copied().unwrap_or(u16::MAX);
+                    [pixel[0], pixel[0], pixel[0], alpha]
+                })
+                .collect();
+
+            match bytemuck::try_cast_vec(expanded) {
+                Ok(bytes) => bytes,
+                Err(err) => return Err(ImageLoadError::DataConversionFailed(err.0)),
+            }
+        }
+
         // Add 4 byte alignment to formats which have 3 channels
         Format::R8G8B8 => data
             .pixels
@@ -62,33 +193,42 @@ pub fn parse_image(data: gltf::image::Data) -> Result<GpuImage, ImageLoadError>
         }
 
         // Simply just returned the data for formats which are nicely paddded
-        Format::R8
-        | Format::R8G8
-        | Format::R8G8B8A8
-        | Format::R16G16B16A16
-        | Format::R32G32B32A32FLOAT => data.pixels,
-
-        // Return an error for unsupported formats
-        f => {
-            return Err(ImageLoadError::UnsupportedFormat(f));
+        Format::R8G8B8A8 | Format::R16G16B16A16 | Format::R32G32B32A32FLOAT => data.pixels,
+    };
+
+    let srgb = color_space == ColorSpace::Srgb;
+
+    // There are no 16-bit sRGB formats, so colour data is decoded on the CPU instead
+    let mut bytes = match data.format {
+        Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 if srgb => {
+            decode_srgb_u16(bytes)
         }
+        _ => bytes,
     };
 
     // Translate the GLTF format into a Vulkan format
     let vulkan_format = match data.format {
-        // Standard 8-bit formats
-        gltf::image::Format::R8 => vk::Format::R8_UNORM,
-        gltf::image::Format::R8G8 => vk::Format::R8G8_UNORM,
-        // 3 Channel formats have poor support, so we should convert to a 4 channel format
-        gltf::image::Format::R8G8B8 => vk::Format::R8G8B8A8_UNORM,
-        gltf::image::Format::R8G8B8A8 => vk::Format::R8G8B8A8_UNORM,
+        // Standard 8-bit formats, colour data uses the sRGB formats which decode it when sampled.
+        // Every 8-bit image has been expanded to 4 channels, as other channel counts have poor
+        // support, especially with sRGB
+        gltf::image::Format::R8
+        | gltf::image::Format::R8G8
+        | gltf::image::Format::R8G8B8
+        | gltf::image::Format::R8G8B8A8
+            if srgb =>
+        {
+            vk::Format::R8G8B8A8_SRGB
+        }
+        gltf::image::Format::R8
+        | gltf::image::Format::R8G8
+        | gltf::image::Format::R8G8B8
+        | gltf::image::Format::R8G8B8A8 => vk::Format::R8G8B8A8_UNORM,
 
-        // 16-bit formats
-        gltf::image::Format::R16 => vk::Format::R16_UNORM,
-        gltf::image::Format::R16G16 => vk::Format::R16G16_UNORM,
-        // 3 Channel formats have poor support, so we should convert to a 4 channel format
-        gltf::image::Format::R16G16B16 => vk::Format::R16G16B16A16_UNORM,
-        gltf::image::Format::R16G16B16A16 => vk::Format::R16G16B16A16_UNORM,
+        // 16-bit formats, which have also been expanded to 4 channels
+        gltf::image::Format::R16
+        | gltf::image::Format::R16G16
+        | gltf::image::Format::R16G16B16
+        | gltf::image::Format::R16G16B16A16 => vk::Format::R16G16B16A16_UNORM,
 
         // 32-Bit Floating point formats
         // 3 Channel formats have poor support, so we should convert to a 4 channel format
@@ -102,9 +242,166 @@ pub fn parse_image(data: gltf::image::Data) -> Result<GpuImage, ImageLoadError>
         z: 1,
     };
 
+    let mip_offsets = generate_mips(&mut bytes, dims.truncate(), vulkan_format);
+
     Ok(GpuImage {
         bytes,
         dims,
         format: vulkan_format,
+        mip_offsets,
     })
 }
+
+// The size in bytes of a single channel, the number of channels and whether the colour channels
+// are sRGB encoded, for each of the formats images are loaded into
+fn texel_layout(format: vk::Format) -> (usize, usize, bool) {
+    match format {
+        vk::Format::R8G8B8A8_UNORM => (1, 4, false),
+        vk::Format::R8G8B8A8_SRGB => (1, 4, true),
+        vk::Format::R16G16B16A16_UNORM => (2, 4, false),
+        vk::Format::R32G32B32A32_SFLOAT => (4, 4, false),
+        _ => unreachable!("Unexpected image format {:?}", format),
+    }
+}
+
+// Appends every level of the mip chain to the full resolution image in `bytes`, each one box
+// filtered from the level above it. Filtering happens on linear values, so sRGB data is decoded
+// first. Returns the offset of each level
+fn generate_mips(bytes: &mut Vec<u8>, dims: glam::UVec2, format: vk::Format) -> Vec<usize> {
+    let (size, channels, srgb) = texel_layout(format);
+
+    // The alpha channel is never sRGB encoded
+    let is_srgb = |channel: usize| srgb && channel < 3;
+
+    let encode = |value: f32, channel: usize, out: &mut Vec<u8>| match size {
+        1 if is_srgb(channel) => out.push((linear_to_srgb(value) * u8::MAX as f32).round() as u8),
+        1 => out.push((value * u8::MAX as f32).round() as u8),
+        2 => out.extend(((value * u16::MAX as f32).round() as u16).to_ne_bytes()),
+        _ => out.extend(value.to_ne_bytes()),
+    };
+
+    let mut values = bytes
+        .chunks_exact(size)
+        .enumerate()
+        .map(|(index, bytes)| decode_channel(bytes, is_srgb(index % channels)))
+        .collect::<Vec<f32>>();
+
+    let levels = 32 - dims.x.max(dims.y).leading_zeros();
+    let mut offsets = vec![0];
+    let mut dims = dims;
+
+    for _ in 1..levels {
+        let next = glam::uvec2((dims.x / 2).max(1), (dims.y / 2).max(1));
+        let mut next_values = Vec::with_capacity((next.x * next.y) as usize * channels);
+
+        // Average each 2x2 block of texels, clamping at the edges of odd sized levels
+        for y in 0..next.y {
+            for x in 0..next.x {
+                for channel in 0..channels {
+                    let mut value = 0.0;
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let sx = (x * 2 + dx).min(dims.x - 1);
+                        let sy = (y * 2 + dy).min(dims.y - 1);
+                        let index = (sy * dims.x + sx) as usize * channels + channel;
+
+                        // Scale before summing, so huge values can't overflow
+                        value += values[index] * 0.25;
+                    }
+
+                    next_values.push(value);
+                }
+            }
+        }
+
+        offsets.push(bytes.len());
+        for (index, &value) in next_values.iter().enumerate() {
+            encode(value, index % channels, bytes);
+        }
+
+        values = next_values;
+        dims = next;
+    }
+
+    offsets
+}
+
+// Reads one channel of a texel, with the channel's size given by the length of `bytes`
+fn decode_channel(bytes: &[u8], srgb: bool) -> f32 {
+    match bytes.len() {
+        1 if srgb => srgb_to_linear(bytes[0] as f32 / u8::MAX as f32),
+        1 => bytes[0] as f32 / u8::MAX as f32,
+        2 => u16::from_ne_bytes([bytes[0], bytes[1]]) as f32 / u16::MAX as f32,
+        _ => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c < 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Converts 16-bit RGBA sRGB encoded pixels to linear values, leaving the alpha channel untouched
+fn decode_srgb_u16(mut bytes: Vec<u8>) -> Vec<u8> {
+    for pixel in bytes.chunks_exact_mut(8) {
+        for value in pixel[..6].chunks_exact_mut(2) {
+            let c = u16::from_ne_bytes([value[0], value[1]]) as f32 / u16::MAX as f32;
+            let linear = (srgb_to_linear(c) * u16::MAX as f32).round() as u16;
+            value.copy_from_slice(&linear.to_ne_bytes());
+        }
+    }
+
+    bytes
+}
+
+pub fn parse_texture(texture: gltf::Texture, image: usize) -> GpuTexture {
+    let sampler = texture.sampler();
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+
+    // Filters are left up to the implementation when not given, so pick the smoothest ones
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+        Some(MagFilter::Linear) | None => vk::Filter::LINEAR,
+    };
+
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST)
+        }
+        Some(MinFilter::NearestMipmapLinear) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR)
+        }
+        Some(MinFilter::LinearMipmapNearest) => {
+            (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST)
+        }
+        Some(MinFilter::Linear | MinFilter::LinearMipmapLinear) | None => {
+            (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR)
+        }
+    };
+
+    GpuTexture {
+        image,
+        address_modes: [
+            address_mode(sampler.wrap_s()),
+            address_mode(sampler.wrap_t()),
+        ],
+        mag_filter,
+        min_filter,
+        mipmap_mode,
+    }
+}
@@ -0,0 +1,137 @@
+use super::{
+    objects::{GpuInstance, GpuMaterial, GpuMesh},
+    visit_nodes,
+};
+use gltf::khr_lights_punctual::Kind;
+
+// The kinds of light defined by KHR_lights_punctual, numbered to match the shader
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightKind {
+    Directional = 0,
+    Point = 1,
+    Spot = 2,
+}
+
+// A light from the KHR_lights_punctual extension, placed in world space.
+// Point and spot light intensities are in candela, and directional ones in lux
+#[derive(Clone)]
+pub struct GpuLight {
+    pub kind: LightKind,
+    pub position: glam::Vec3A,
+    // The direction the light points in, down the node's local -z axis
+    pub direction: glam::Vec3A,
+
+    pub color: glam::Vec3A,
+    pub intensity: f32,
+    pub range: Option<f32>,
+
+    // Angles from the spot light's axis where its falloff starts and ends
+    pub inner_cone_angle: f32,
+    pub outer_cone_angle: f32,
+}
+
+pub fn load_lights(scene: &gltf::Scene) -> Vec<GpuLight> {
+    let mut lights = Vec::new();
+
+    visit_nodes(scene, |node, transform| {
+        let Some(light) = node.light() else {
+            return;
+        };
+
+        let (kind, inner_cone_angle, outer_cone_angle) = match light.kind() {
+            Kind::Directional => (LightKind::Directional, 0.0, 0.0),
+            Kind::Point => (LightKind::Point, 0.0, 0.0),
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (LightKind::Spot, inner_cone_angle, outer_cone_angle),
+        };
+
+        lights.push(GpuLight {
+            kind,
+            position: transform.transform_point3a(glam::Vec3A::ZERO),
+            direction: transform
+                .transform_vector3a(glam::Vec3A::NEG_Z)
+                .normalize_or_zero(),
+
+            color: glam::Vec3A::from_array(light.color()),
+            intensity: light.intensity(),
+            range: light.range(),
+
+            inner_cone_angle,
+            outer_cone_angle,
+        });
+    });
+
+    log::info!("Found {} punctual lights", lights.len());
+
+    lights
+}
+
+// An emissive triangle placed in world space, which next event estimation picks by its power
+pub struct EmissiveTriangle {
+    pub vertices: [glam::Vec3A; 3],
+    // The material's emissive factor, its texture is sampled where the triangle is hit
+    pub emissive: glam::Vec3A,
+
+    // Probability of picking this triangle or any before it
+    pub cdf: f32,
+
+    // The mesh, geometry within the mesh and triangle this was taken from
+    pub mesh: usize,
+    pub geometry: usize,
+    pub primitive: u32,
+}
+
+// Collects every triangle of every instance of an emissive primitive, returning them along with
+// the sum of their luminance times area
+pub fn emissive_triangles(
+    meshes: &[GpuMesh],
+    instances: &[GpuInstance],
+    materials: &[GpuMaterial],
+) -> (Vec<EmissiveTriangle>, f32) {
+    let mut triangles = Vec::new();
+    let mut total_power = 0.0;
+
+    for instance in instances {
+        let primitives = meshes[instance.mesh].primitives.iter();
+
+        for (geometry, primitive) in primitives.enumerate() {
+            let emissive = materials[primitive.material].emissive;
+            let luminance = emissive.dot(glam::vec3a(0.2126, 0.7152, 0.0722));
+            if luminance <= 0.0 {
+                continue;
+            }
+
+            for (index, triangle) in primitive.indices.chunks_exact(3).enumerate() {
+                let vertices = [triangle[0], triangle[1], triangle[2]].map(|index| {
+                    let offset = index as usize * 3;
+                    let position = glam::Vec3A::from_slice(&primitive.vertices[offset..offset + 3]);
+                    instance.transform.transform_point3a(position)
+                });
+
+                let [v0, v1, v2] = vertices;
+                let area = 0.5 * (v1 - v0).cross(v2 - v0).length();
+                if area <= 0.0 {
+                    continue;
+                }
+
+                total_power += luminance * area;
+                triangles.push(EmissiveTriangle {
+                    vertices,
+                    emissive,
+                    cdf: total_power,
+                    mesh: instance.mesh,
+                    geometry,
+                    primitive: index as u32,
+                });
+            }
+        }
+    }
+
+    for triangle in &mut triangles {
+        triangle.cdf /= total_power;
+    }
+
+    (triangles, total_power)
+}
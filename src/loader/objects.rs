@@ -1,65 +1,781 @@
-use gltf::Document;
+use super::{
+    images::{ColorSpace, TextureSet},
+    visit_nodes,
+};
+use glam::{Vec2, Vec3, Vec4};
+use gltf::{
+    accessor::{DataType, Iter},
+    material::AlphaMode,
+    mesh::Mode,
+    Semantic,
+};
+use std::collections::HashMap;
+use thiserror::Error;
 
-pub struct GpuObject {
+// A texture used by a material, and which set of texture coordinates it's sampled with.
+// The texture indexes into the scene's textures, rather than the glTF document's
+#[derive(Clone, Copy)]
+pub struct TextureSlot {
+    pub texture: usize,
+    pub tex_coord: u32,
+}
+
+impl TextureSlot {
+    fn new(
+        textures: &mut TextureSet,
+        texture: gltf::Texture,
+        tex_coord: u32,
+        color_space: ColorSpace,
+    ) -> Self {
+        // Only the first two sets of texture coordinates are loaded
+        if tex_coord > 1 {
+            log::warn!("Texture coordinate set {} isn't supported", tex_coord);
+        }
+
+        Self {
+            texture: textures.insert(texture, color_space),
+            tex_coord: tex_coord.min(1),
+        }
+    }
+}
+
+// A glTF mesh, loaded once however many times it's drawn
+pub struct GpuMesh {
+    pub primitives: Vec<GpuPrimitive>,
+}
+
+pub struct GpuPrimitive {
     pub vertices: Vec<f32>,
     pub indices: Vec<u32>,
 
-    pub transform: glam::Mat4,
+    // Shading attributes, with one entry for every position in `vertices`
+    pub normals: Vec<Vec3>,
+    pub tangents: Vec<Vec4>,
+    pub tex_coords: [Vec<Vec2>; 2],
+
+    // Index into the scene's materials, which are shared between primitives
+    pub material: usize,
+}
 
+#[derive(Clone)]
+pub struct GpuMaterial {
     pub base_color: glam::Vec3A,
     pub emissive: glam::Vec3A,
     pub roughness: f32,
     pub metallic: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+
+    // The base colour's alpha, which the base colour texture's alpha is multiplied by
+    pub alpha: f32,
+    pub alpha_mode: AlphaMode,
+    // Masked surfaces are only hit where their alpha is at least this
+    pub alpha_cutoff: f32,
+
+    pub base_color_texture: Option<TextureSlot>,
+    pub metallic_roughness_texture: Option<TextureSlot>,
+    pub normal_texture: Option<TextureSlot>,
+    pub emissive_texture: Option<TextureSlot>,
+    pub occlusion_texture: Option<TextureSlot>,
+}
+
+// A placement of one of the scene's meshes in the world
+pub struct GpuInstance {
+    pub mesh: usize,
+    pub transform: glam::Mat4,
+}
+
+// Everything drawn in a scene, with instances referring to meshes, and primitives to materials
+pub struct SceneObjects {
+    pub meshes: Vec<GpuMesh>,
+    pub instances: Vec<GpuInstance>,
+    pub materials: Vec<GpuMaterial>,
+}
+
+#[derive(Error, Debug)]
+pub enum MeshLoadError {
+    #[error("Unsupported primitive mode {0:?}")]
+    UnsupportedMode(Mode),
+    #[error("Primitive has no vertex positions")]
+    MissingPositions,
+    #[error("Accessor for {semantic} holds {found} items, expected {expected}")]
+    AccessorTooShort {
+        semantic: String,
+        found: usize,
+        expected: usize,
+    },
+    #[error("Index {index} is out of range of the {count} vertices")]
+    IndexOutOfRange { index: u32, count: usize },
+    #[error("Accessor {0} doesn't exist or has an unsupported type")]
+    InvalidAccessor(u64),
+    #[error("Primitive has no triangles")]
+    Empty,
+    #[error("None of the mesh's {0} primitives could be loaded")]
+    NoPrimitives(usize),
+    #[error("The scene has {0} meshes, but none of them could be loaded")]
+    NoMeshes(usize),
 }
 
-pub fn load_objects(document: &Document, buffers: &[gltf::buffer::Data]) -> Vec<GpuObject> {
-    let mut objects = Vec::new();
+pub fn load_meshes(
+    document: &gltf::Document,
+    scene: &gltf::Scene,
+    buffers: &[gltf::buffer::Data],
+    textures: &mut TextureSet,
+) -> Result<SceneObjects, MeshLoadError> {
+    let mut meshes = Vec::new();
+    let mut instances = Vec::new();
+    let mut materials = Vec::new();
+
+    // The index each glTF mesh and material was loaded at. Meshes without a single primitive
+    // that could be loaded aren't drawn, and primitives without a material share the default one
+    let mut loaded_meshes: HashMap<usize, Option<usize>> = HashMap::new();
+    let mut skipped_meshes = 0;
+    let mut loaded_materials: HashMap<Option<usize>, usize> = HashMap::new();
+
+    // Nodes outside of the scene aren't drawn, and nodes shared by several parents are drawn
+    // once for each of them
+    visit_nodes(scene, |node, transform| {
+        let Some(mesh) = node.mesh() else {
+            return;
+        };
 
-    for node in document.nodes() {
-        if let Some(mesh) = node.mesh() {
+        let index = *loaded_meshes.entry(mesh.index()).or_insert_with(|| {
+            // A primitive that can't be loaded is left out, rather than failing the rest of the mesh
+            let mut primitives = Vec::new();
             for primitive in mesh.primitives() {
-                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let material = primitive.material();
+                let material = *loaded_materials.entry(material.index()).or_insert_with(|| {
+                    materials.push(load_material(&material, textures));
+                    materials.len() - 1
+                });
 
-                let transform = glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+                match load_primitive(&primitive, buffers, material) {
+                    Ok(loaded) => primitives.push(loaded),
+                    Err(err) => log::warn!(
+                        "Skipping primitive {} of mesh {} ({}) : {}",
+                        primitive.index(),
+                        mesh.index(),
+                        mesh.name().unwrap_or("unnamed"),
+                        err
+                    ),
+                }
+            }
 
-                let vertices = reader
-                    .read_positions()
-                    .unwrap()
-                    .into_iter()
-                    .flatten()
-                    .collect::<Vec<f32>>();
+            if primitives.is_empty() {
+                let err = MeshLoadError::NoPrimitives(mesh.primitives().len());
+                log::warn!(
+                    "Skipping mesh {} ({}) : {}",
+                    mesh.index(),
+                    mesh.name().unwrap_or("unnamed"),
+                    err
+                );
+                skipped_meshes += 1;
+                return None;
+            }
 
-                let indices = reader
-                    .read_indices()
-                    .unwrap()
-                    .into_u32()
-                    .collect::<Vec<u32>>();
+            meshes.push(GpuMesh { primitives });
+            Some(meshes.len() - 1)
+        });
 
-                let pbr = primitive.material().pbr_metallic_roughness();
+        let Some(mesh) = index else {
+            return;
+        };
 
-                let base_color = glam::Vec3A::from_slice(&pbr.base_color_factor());
-                let roughness = pbr.roughness_factor();
-                let metallic = pbr.metallic_factor();
-                let emissive = glam::Vec3A::from_array(primitive.material().emissive_factor());
+        let transforms = match instance_transforms(node, document, buffers) {
+            Ok(Some(locals)) => locals.into_iter().map(|local| transform * local).collect(),
+            Ok(None) => vec![transform],
+            Err(err) => {
+                log::warn!(
+                    "Ignoring EXT_mesh_gpu_instancing on node {} : {}",
+                    node.index(),
+                    err
+                );
+                vec![transform]
+            }
+        };
 
-                let object = GpuObject {
-                    vertices,
-                    indices,
+        for transform in transforms {
+            instances.push(GpuInstance { mesh, transform });
+        }
+    });
 
-                    transform,
+    // Drawing nothing at all would only hide why the scene is empty
+    if meshes.is_empty() && skipped_meshes > 0 {
+        return Err(MeshLoadError::NoMeshes(skipped_meshes));
+    }
 
-                    base_color,
-                    emissive,
-                    roughness,
-                    metallic,
-                };
+    log::info!(
+        "Loaded {} meshes and {} materials, drawn as {} instances",
+        meshes.len(),
+        materials.len(),
+        instances.len()
+    );
 
-                objects.push(object);
+    Ok(SceneObjects {
+        meshes,
+        instances,
+        materials,
+    })
+}
+
+// Reads the per-instance transforms of a node using EXT_mesh_gpu_instancing, which are relative to
+// the node's own transform. Returns None if the node doesn't use the extension
+// Source : https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Vendor/EXT_mesh_gpu_instancing
+fn instance_transforms(
+    node: &gltf::Node,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Option<Vec<glam::Mat4>>, MeshLoadError> {
+    let Some(extension) = node.extension_value("EXT_mesh_gpu_instancing") else {
+        return Ok(None);
+    };
+
+    let attribute = |name: &str| extension["attributes"][name].as_u64();
+    let accessor = |index: u64| {
+        document
+            .accessors()
+            .nth(index as usize)
+            .ok_or(MeshLoadError::InvalidAccessor(index))
+    };
+    let get_buffer_data = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|data| &data[..]);
+
+    // Every attribute holds one item for each instance
+    let attributes = ["TRANSLATION", "ROTATION", "SCALE"].map(attribute);
+    let Some(&first) = attributes.iter().flatten().next() else {
+        return Ok(None);
+    };
+    let count = accessor(first)?.count();
+
+    let mut translations = vec![[0.0; 3]; count];
+    if let Some(index) = attributes[0] {
+        let iter = Iter::<[f32; 3]>::new(accessor(index)?, get_buffer_data);
+        translations = read_exact(iter, count, "TRANSLATION".to_string())?;
+    }
+
+    // Rotations can also be stored as normalized integers
+    let mut rotations = vec![[0.0, 0.0, 0.0, 1.0]; count];
+    if let Some(index) = attributes[1] {
+        let rotation = accessor(index)?;
+        let name = "ROTATION".to_string();
+        rotations = match rotation.data_type() {
+            DataType::F32 => read_exact(Iter::new(rotation, get_buffer_data), count, name)?,
+            DataType::I8 => {
+                let iter = Iter::<[i8; 4]>::new(rotation, get_buffer_data);
+                let iter = iter.map(|iter| iter.map(|q| q.map(|x| (x as f32 / 127.0).max(-1.0))));
+                read_exact(iter, count, name)?
             }
+            DataType::I16 => {
+                let iter = Iter::<[i16; 4]>::new(rotation, get_buffer_data);
+                let iter = iter.map(|iter| iter.map(|q| q.map(|x| (x as f32 / 32767.0).max(-1.0))));
+                read_exact(iter, count, name)?
+            }
+            _ => return Err(MeshLoadError::InvalidAccessor(index)),
+        };
+    }
+
+    let mut scales = vec![[1.0; 3]; count];
+    if let Some(index) = attributes[2] {
+        let iter = Iter::<[f32; 3]>::new(accessor(index)?, get_buffer_data);
+        scales = read_exact(iter, count, "SCALE".to_string())?;
+    }
+
+    let transforms = (0..count)
+        .map(|i| {
+            glam::Mat4::from_scale_rotation_translation(
+                Vec3::from(scales[i]),
+                glam::Quat::from_array(rotations[i]).normalize(),
+                Vec3::from(translations[i]),
+            )
+        })
+        .collect();
+
+    Ok(Some(transforms))
+}
+
+fn load_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    material: usize,
+) -> Result<GpuPrimitive, MeshLoadError> {
+    let mode = primitive.mode();
+    if !matches!(
+        mode,
+        Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+    ) {
+        return Err(MeshLoadError::UnsupportedMode(mode));
+    }
+
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+    let Some(positions) = primitive.get(&Semantic::Positions) else {
+        return Err(MeshLoadError::MissingPositions);
+    };
+    let count = positions.count();
+
+    // Every attribute is read through `read_exact`, which handles sparse accessors
+    let mut vertices = read_exact(
+        reader.read_positions(),
+        count,
+        Semantic::Positions.to_string(),
+    )?
+    .into_iter()
+    .flatten()
+    .collect::<Vec<f32>>();
+
+    let indices = primitive
+        .indices()
+        .map(|accessor| {
+            read_exact(
+                reader.read_indices().map(|indices| indices.into_u32()),
+                accessor.count(),
+                "indices".to_string(),
+            )
+        })
+        .transpose()?;
+    let mut indices = triangle_list(mode, indices, count)?;
+
+    let mut tex_coords = [Vec::new(), Vec::new()];
+    for (set, tex_coords) in tex_coords.iter_mut().enumerate() {
+        let set = set as u32;
+        let semantic = Semantic::TexCoords(set);
+        *tex_coords = if primitive.get(&semantic).is_some() {
+            let iter = reader.read_tex_coords(set);
+            read_exact(
+                iter.map(|iter| iter.into_f32().map(Vec2::from)),
+                count,
+                semantic.to_string(),
+            )?
+        } else {
+            vec![Vec2::ZERO; count]
+        };
+    }
+
+    let has_tex_coords = primitive.get(&Semantic::TexCoords(0)).is_some();
+
+    let mut tangents = primitive
+        .get(&Semantic::Tangents)
+        .map(|_| {
+            let iter = reader.read_tangents();
+            read_exact(
+                iter.map(|iter| iter.map(Vec4::from)),
+                count,
+                Semantic::Tangents.to_string(),
+            )
+        })
+        .transpose()?;
+
+    let normals = match primitive.get(&Semantic::Normals) {
+        Some(_) => {
+            let iter = reader.read_normals();
+            read_exact(
+                iter.map(|iter| iter.map(Vec3::from)),
+                count,
+                Semantic::Normals.to_string(),
+            )?
+        }
+
+        // The spec asks for flat shading when normals are missing, which needs every
+        // triangle to have its own vertices. Tangents can't be given without normals
+        None => {
+            unweld(&mut vertices, &mut indices, &mut tex_coords);
+            tangents = None;
+            flat_normals(&vertices, &indices)
+        }
+    };
+
+    let tangents = match tangents {
+        Some(tangents) => tangents,
+        None => generate_tangents(
+            &vertices,
+            &indices,
+            &normals,
+            has_tex_coords.then_some(tex_coords[0].as_slice()),
+        ),
+    };
+
+    Ok(GpuPrimitive {
+        vertices,
+        indices,
+
+        normals,
+        tangents,
+        tex_coords,
+
+        material,
+    })
+}
+
+fn load_material(material: &gltf::Material, textures: &mut TextureSet) -> GpuMaterial {
+    let pbr = material.pbr_metallic_roughness();
+
+    let [r, g, b, alpha] = pbr.base_color_factor();
+    let base_color = glam::vec3a(r, g, b);
+    let roughness = pbr.roughness_factor();
+    let metallic = pbr.metallic_factor();
+    let emissive = glam::Vec3A::from_array(material.emissive_factor());
+
+    // Base colour and emission are colours, while the other textures hold data
+    let mut slot = |texture, tex_coord, color_space| {
+        TextureSlot::new(textures, texture, tex_coord, color_space)
+    };
+
+    let base_color_texture = pbr
+        .base_color_texture()
+        .map(|info| slot(info.texture(), info.tex_coord(), ColorSpace::Srgb));
+    let emissive_texture = material
+        .emissive_texture()
+        .map(|info| slot(info.texture(), info.tex_coord(), ColorSpace::Srgb));
+    let metallic_roughness_texture = pbr
+        .metallic_roughness_texture()
+        .map(|info| slot(info.texture(), info.tex_coord(), ColorSpace::Linear));
+
+    let normal = material.normal_texture();
+    let normal_texture = normal
+        .as_ref()
+        .map(|info| slot(info.texture(), info.tex_coord(), ColorSpace::Linear));
+
+    let occlusion = material.occlusion_texture();
+    let occlusion_texture = occlusion
+        .as_ref()
+        .map(|info| slot(info.texture(), info.tex_coord(), ColorSpace::Linear));
+
+    GpuMaterial {
+        base_color,
+        emissive,
+        roughness,
+        metallic,
+        normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+        occlusion_strength: occlusion
+            .as_ref()
+            .map_or(1.0, |occlusion| occlusion.strength()),
+
+        alpha,
+        alpha_mode: material.alpha_mode(),
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+
+        base_color_texture,
+        metallic_roughness_texture,
+        normal_texture,
+        emissive_texture,
+        occlusion_texture,
+    }
+}
+
+// Reads exactly `count` items from an accessor. Sparse accessors without a buffer view never run
+// out of items and give a broken size hint, so they can't be collected directly
+fn read_exact<T>(
+    iter: Option<impl Iterator<Item = T>>,
+    count: usize,
+    semantic: String,
+) -> Result<Vec<T>, MeshLoadError> {
+    let items = match iter {
+        Some(mut iter) => (0..count).map_while(|_| iter.next()).collect(),
+        None => Vec::new(),
+    };
+
+    if items.len() == count {
+        Ok(items)
+    } else {
+        Err(MeshLoadError::AccessorTooShort {
+            semantic,
+            found: items.len(),
+            expected: count,
+        })
+    }
+}
+
+// Checks a primitive's indices against its `count` vertices, and converts them into a triangle list
+fn triangle_list(
+    mode: Mode,
+    indices: Option<Vec<u32>>,
+    count: usize,
+) -> Result<Vec<u32>, MeshLoadError> {
+    // Primitives without indices draw their vertices in order
+    let indices = indices.unwrap_or_else(|| (0..count as u32).collect());
+
+    if let Some(&index) = indices.iter().find(|&&index| index as usize >= count) {
+        return Err(MeshLoadError::IndexOutOfRange { index, count });
+    }
+
+    // Empty primitives can't be built into an acceleration structure
+    let indices = triangulate(mode, &indices);
+    if indices.is_empty() {
+        return Err(MeshLoadError::Empty);
+    }
+
+    Ok(indices)
+}
+
+// Converts the indices of a primitive into a triangle list. Strips alternate their winding, so
+// every other triangle is flipped to keep them all facing the same way
+fn triangulate(mode: Mode, indices: &[u32]) -> Vec<u32> {
+    let triangles = indices.len().saturating_sub(2);
+
+    match mode {
+        Mode::TriangleStrip => (0..triangles)
+            .map(|i| {
+                if i % 2 == 0 {
+                    [indices[i], indices[i + 1], indices[i + 2]]
+                } else {
+                    [indices[i + 1], indices[i], indices[i + 2]]
+                }
+            })
+            // Strips repeat indices to jump between rows, which leaves empty triangles behind
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .flatten()
+            .collect(),
+
+        Mode::TriangleFan => (0..triangles)
+            .flat_map(|i| [indices[i + 1], indices[i + 2], indices[0]])
+            .collect(),
+
+        // Anything left over after the last whole triangle is ignored
+        _ => indices[..indices.len() - indices.len() % 3].to_vec(),
+    }
+}
+
+// Gives every triangle its own copy of its vertices, so they stop sharing attributes
+fn unweld(vertices: &mut Vec<f32>, indices: &mut Vec<u32>, tex_coords: &mut [Vec<Vec2>; 2]) {
+    *vertices = indices
+        .iter()
+        .flat_map(|&index| {
+            let offset = index as usize * 3;
+            [vertices[offset], vertices[offset + 1], vertices[offset + 2]]
+        })
+        .collect();
+
+    for set in tex_coords.iter_mut() {
+        *set = indices.iter().map(|&index| set[index as usize]).collect();
+    }
+
+    *indices = (0..indices.len() as u32).collect();
+}
+
+fn flat_normals(vertices: &[f32], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::Y; vertices.len() / 3];
+
+    for triangle in indices.chunks_exact(3) {
+        let [v0, v1, v2] = [triangle[0], triangle[1], triangle[2]]
+            .map(|index| Vec3::from_slice(&vertices[index as usize * 3..]));
+
+        let normal = (v1 - v0).cross(v2 - v0).normalize_or_zero();
+        for &index in triangle {
+            normals[index as usize] = normal;
         }
     }
 
-    for _mesh in document.meshes() {}
+    normals
+}
+
+// Generates MikkTSpace tangents from the first set of texture coordinates, which is what normal
+// maps are baked against. Meshes without texture coordinates get an arbitrary tangent instead
+fn generate_tangents(
+    vertices: &[f32],
+    indices: &[u32],
+    normals: &[Vec3],
+    tex_coords: Option<&[Vec2]>,
+) -> Vec<Vec4> {
+    let fallback = normals
+        .iter()
+        .map(|normal| {
+            normal
+                .any_orthogonal_vector()
+                .normalize_or_zero()
+                .extend(1.0)
+        })
+        .collect::<Vec<Vec4>>();
+
+    let Some(tex_coords) = tex_coords else {
+        return fallback;
+    };
+
+    let mut geometry = TangentGeometry {
+        vertices,
+        indices,
+        normals,
+        tex_coords,
+        tangents: fallback.clone(),
+    };
 
-    objects
+    if bevy_mikktspace::generate_tangents(&mut geometry) {
+        geometry.tangents
+    } else {
+        log::warn!("Failed to generate tangents for a mesh");
+        fallback
+    }
+}
+
+struct TangentGeometry<'a> {
+    vertices: &'a [f32],
+    indices: &'a [u32],
+    normals: &'a [Vec3],
+    tex_coords: &'a [Vec2],
+    tangents: Vec<Vec4>,
+}
+
+impl TangentGeometry<'_> {
+    fn index(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert] as usize
+    }
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        let offset = self.index(face, vert) * 3;
+        [
+            self.vertices[offset],
+            self.vertices[offset + 1],
+            self.vertices[offset + 2],
+        ]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.normals[self.index(face, vert)].into()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.tex_coords[self.index(face, vert)].into()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let index = self.index(face, vert);
+        self.tangents[index] = Vec4::from(tangent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect()
+    }
+
+    #[test]
+    fn triangles_drop_trailing_indices() {
+        let indices = triangulate(Mode::Triangles, &[0, 1, 2, 2, 1, 3, 4, 5]);
+        assert_eq!(triangles(&indices), [[0, 1, 2], [2, 1, 3]]);
+    }
+
+    #[test]
+    fn strips_keep_their_winding() {
+        // A strip along a row of quads, where every other triangle is flipped
+        let indices = triangulate(Mode::TriangleStrip, &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(
+            triangles(&indices),
+            [[0, 1, 2], [2, 1, 3], [2, 3, 4], [4, 3, 5]]
+        );
+    }
+
+    #[test]
+    fn strips_drop_degenerate_triangles() {
+        // Two strips joined by repeating the last index of one and the first index of the other.
+        // Joining takes an even number of triangles, so the second strip keeps its winding
+        let indices = triangulate(Mode::TriangleStrip, &[0, 1, 2, 3, 3, 4, 4, 5, 6, 7]);
+        assert_eq!(
+            triangles(&indices),
+            [[0, 1, 2], [2, 1, 3], [4, 5, 6], [6, 5, 7]]
+        );
+    }
+
+    #[test]
+    fn fans_share_their_first_vertex() {
+        let indices = triangulate(Mode::TriangleFan, &[0, 1, 2, 3, 4]);
+        assert_eq!(triangles(&indices), [[1, 2, 0], [2, 3, 0], [3, 4, 0]]);
+    }
+
+    #[test]
+    fn short_inputs_have_no_triangles() {
+        for mode in [Mode::Triangles, Mode::TriangleStrip, Mode::TriangleFan] {
+            assert!(triangulate(mode, &[]).is_empty());
+            assert!(triangulate(mode, &[0, 1]).is_empty());
+            assert!(matches!(
+                triangle_list(mode, Some(vec![0, 1]), 2),
+                Err(MeshLoadError::Empty)
+            ));
+        }
+    }
+
+    #[test]
+    fn unindexed_primitives_draw_vertices_in_order() {
+        let indices = triangle_list(Mode::Triangles, None, 6).unwrap();
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5]);
+
+        let indices = triangle_list(Mode::TriangleStrip, None, 4).unwrap();
+        assert_eq!(triangles(&indices), [[0, 1, 2], [2, 1, 3]]);
+    }
+
+    #[test]
+    fn indices_past_the_vertices_are_rejected() {
+        let result = triangle_list(Mode::Triangles, Some(vec![0, 1, 3]), 3);
+        assert!(matches!(
+            result,
+            Err(MeshLoadError::IndexOutOfRange { index: 3, count: 3 })
+        ));
+    }
+
+    #[test]
+    fn short_accessors_are_rejected() {
+        let result = read_exact(Some([1, 2, 3].into_iter()), 4, "POSITION".to_string());
+        assert!(matches!(
+            result,
+            Err(MeshLoadError::AccessorTooShort {
+                found: 3,
+                expected: 4,
+                ..
+            })
+        ));
+
+        let result = read_exact(None::<std::iter::Empty<u32>>, 2, "NORMAL".to_string());
+        assert!(matches!(
+            result,
+            Err(MeshLoadError::AccessorTooShort { found: 0, .. })
+        ));
+
+        // Sparse accessors can go on forever, so only the items asked for are read
+        let items = read_exact(Some(std::iter::repeat(7)), 3, "TEXCOORD_0".to_string()).unwrap();
+        assert_eq!(items, [7, 7, 7]);
+    }
+
+    #[test]
+    fn unwelding_gives_every_corner_its_own_vertex() {
+        let mut vertices = vec![
+            0.0, 0.0, 0.0, //
+            1.0, 0.0, 0.0, //
+            0.0, 1.0, 0.0, //
+            1.0, 1.0, 0.0,
+        ];
+        let mut indices = vec![0, 1, 2, 2, 1, 3];
+        let uvs = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(1.0, 1.0),
+        ];
+        let mut tex_coords = [uvs.clone(), vec![Vec2::ZERO; 4]];
+
+        let corners = indices.clone();
+        unweld(&mut vertices, &mut indices, &mut tex_coords);
+
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(vertices.len(), 6 * 3);
+        for (vertex, &corner) in corners.iter().enumerate() {
+            let corner = corner as usize;
+            assert_eq!(vertices[vertex * 3], (corner % 2) as f32);
+            assert_eq!(vertices[vertex * 3 + 1], (corner / 2) as f32);
+            assert_eq!(tex_coords[0][vertex], uvs[corner]);
+            assert_eq!(tex_coords[1][vertex], Vec2::ZERO);
+        }
+    }
 }
@@ -2,6 +2,7 @@ use input::{Input, Inputs};
 use interface::Interface;
 use loader::SceneLoader;
 use render::Renderer;
+use std::time::Instant;
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
@@ -9,10 +10,13 @@ use winit::{
 };
 use world::World;
 
+mod bvh;
+mod cli;
 mod input;
 mod interface;
 mod loader;
 mod render;
+mod surfaces;
 mod vulkan;
 mod world;
 
@@ -20,14 +24,26 @@ fn main() {
     env_logger::builder()
         .filter_level(log::LevelFilter::Info)
         .init();
+
+    // Offline rendering mode, which doesn't need a window
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).map(String::as_str) == Some("render") {
+        if let Err(err) = cli::run(&args[2..]) {
+            log::error!("Render failed : {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     SceneLoader::init();
     let event_loop = EventLoop::new().unwrap();
     let window = Window::new(&event_loop).unwrap();
     window.set_resizable(false);
-    let inputs = Inputs::new();
+    let mut inputs = Inputs::new();
     let mut renderer = Renderer::new(&window);
     let mut world = World::new();
     let mut interface = Interface::new(&window);
+    let mut last_update = Instant::now();
 
     event_loop
         .run(|event, target| {
@@ -46,7 +62,11 @@ fn main() {
 
                 // Main application loop
                 Event::AboutToWait => {
-                    world.update(&inputs);
+                    let now = Instant::now();
+                    let dt = now.duration_since(last_update).as_secs_f32();
+                    last_update = now;
+
+                    world.update(&mut inputs, dt);
                     interface.update(&window, &mut world);
                     renderer.render(&mut world, &mut interface);
                 }
@@ -1,6 +1,9 @@
-use self::{frame::Frames, painter::InterfacePainter, raytracer::Raytracer};
+use self::{
+    frame::Frames, painter::InterfacePainter, raytracer::Raytracer, tonemapper::Tonemapper,
+};
 use crate::{
     interface::Interface,
+    loader::SceneLoader,
     vulkan::{context::Context, display::Display},
     world::World,
 };
@@ -8,8 +11,13 @@ use std::sync::Arc;
 use winit::window::Window;
 
 mod frame;
+mod offline;
 mod painter;
 mod raytracer;
+mod reference;
+mod tonemapper;
+
+pub use self::{offline::OfflineRenderer, reference::ReferenceTracer, tonemapper::tonemap};
 
 pub struct Renderer {
     context: Arc<Context>,
@@ -17,6 +25,7 @@ pub struct Renderer {
     frames: Frames,
 
     raytracer: Raytracer,
+    tonemapper: Tonemapper,
     painter: InterfacePainter,
 }
 
@@ -27,6 +36,7 @@ impl Renderer {
         let frames = Frames::new(context.clone(), display.clone());
 
         let raytracer = Raytracer::new(context.clone());
+        let tonemapper = Tonemapper::new(context.clone());
         let painter = InterfacePainter::new(context.clone(), &display);
 
         Self {
@@ -35,6 +45,7 @@ impl Renderer {
             frames,
 
             raytracer,
+            tonemapper,
             painter,
         }
     }
@@ -44,11 +55,29 @@ impl Renderer {
         let cmds = frame.allocate_command_list();
 
         cmds.begin();
+        // The world keeps the parts of a newly loaded scene it needs before the raytracer takes it
+        if let Some(mut scene) = SceneLoader::poll() {
+            world.load_scene(&mut scene);
+            if let Err(err) = self.raytracer.load_scene(self.context.clone(), scene) {
+                log::error!("Failed to load scene : {}", err);
+            }
+        }
+
+        if let Some(environment) = SceneLoader::poll_environment() {
+            self.raytracer
+                .load_environment(self.context.clone(), &environment);
+        }
+
         self.raytracer.run(&cmds, &frame, world);
+        if let Some(output) = self.raytracer.output() {
+            self.tonemapper.run(&cmds, &frame, world, &output.view);
+        }
         self.painter.draw(&cmds, &frame, interface);
         cmds.end();
 
         frame.submit(&[cmds]);
+
+        world.stats = self.raytracer.stats();
     }
 }
 
@@ -6,6 +6,8 @@ use crate::vulkan::{
     sync::{Fence, Semaphore},
 };
 
+use ash::vk;
+use glam::UVec2;
 use std::sync::Arc;
 
 pub struct Frame {
@@ -15,24 +17,56 @@ pub struct Frame {
     command_pool: CommandPool,
 }
 
+// A plain image which frames are rendered into when there is no window to present to
+pub struct Offscreen {
+    pub image: Image,
+    pub view: ImageView,
+    pub dims: UVec2,
+}
+
+pub enum FrameTarget {
+    Display(Arc<Display>),
+    Offscreen(Offscreen),
+}
+
 pub struct FrameRef<'a> {
     pub frame: &'a Frame,
     pub context: &'a Arc<Context>,
-    pub display: &'a Display,
-    swapchain_index: u32,
+    pub target: &'a FrameTarget,
+    index: u32,
 }
 
 impl<'a> FrameRef<'a> {
     pub fn index(&self) -> usize {
-        self.swapchain_index as usize
+        self.index as usize
     }
 
     pub fn image(&self) -> &Image {
-        &self.display.images[self.index()]
+        match self.target {
+            FrameTarget::Display(display) => &display.images[self.index()],
+            FrameTarget::Offscreen(offscreen) => &offscreen.image,
+        }
     }
 
     pub fn image_view(&self) -> &ImageView {
-        &self.display.views[self.index()]
+        match self.target {
+            FrameTarget::Display(display) => &display.views[self.index()],
+            FrameTarget::Offscreen(offscreen) => &offscreen.view,
+        }
+    }
+
+    pub fn dims(&self) -> UVec2 {
+        match self.target {
+            FrameTarget::Display(display) => display.dims,
+            FrameTarget::Offscreen(offscreen) => offscreen.dims,
+        }
+    }
+
+    pub fn dpi(&self) -> f32 {
+        match self.target {
+            FrameTarget::Display(display) => display.dpi,
+            FrameTarget::Offscreen(_) => 1.0,
+        }
     }
 
     pub fn allocate_command_list(&self) -> CommandList {
@@ -40,34 +74,71 @@ impl<'a> FrameRef<'a> {
     }
 
     pub fn submit(&mut self, cmds: &[CommandList]) {
-        self.context.submit(
-            cmds,
-            Some(&self.frame.swapchain_ready),
-            Some(&self.frame.rendering_finished),
-            Some(&self.frame.inflight),
-        );
-
-        self.display.present(
-            &self.context,
-            self.swapchain_index,
-            &self.frame.rendering_finished,
-        );
+        match self.target {
+            FrameTarget::Display(display) => {
+                self.context.submit(
+                    cmds,
+                    Some(&self.frame.swapchain_ready),
+                    Some(&self.frame.rendering_finished),
+                    Some(&self.frame.inflight),
+                );
+
+                display.present(self.context, self.index, &self.frame.rendering_finished);
+            }
+
+            // Nothing to present, so only the fence needs signalling
+            FrameTarget::Offscreen(_) => {
+                self.context
+                    .submit(cmds, None, None, Some(&self.frame.inflight));
+            }
+        }
     }
 }
 
 pub struct Frames {
     context: Arc<Context>,
-    display: Arc<Display>,
+    target: FrameTarget,
     frames: Vec<Frame>,
 
     counter: usize,
 }
 
 impl Frames {
+    pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
     pub fn new(context: Arc<Context>, display: Arc<Display>) -> Self {
-        let mut frames = Vec::with_capacity(display.frames_in_flight());
+        let frames_in_flight = display.frames_in_flight();
+        Self::with_target(context, FrameTarget::Display(display), frames_in_flight)
+    }
+
+    pub fn offscreen(context: Arc<Context>, dims: UVec2) -> Self {
+        let image = Image::new(
+            context.clone(),
+            dims.extend(1),
+            Self::OFFSCREEN_FORMAT,
+            vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            "Offscreen Render Target",
+        );
+
+        let view = ImageView::new(
+            context.clone(),
+            &image,
+            Self::OFFSCREEN_FORMAT,
+            Image::default_subresource(vk::ImageAspectFlags::COLOR),
+        );
+
+        let offscreen = Offscreen { image, view, dims };
+
+        // There is only a single target image, so only one frame can be in flight at a time
+        Self::with_target(context, FrameTarget::Offscreen(offscreen), 1)
+    }
+
+    fn with_target(context: Arc<Context>, target: FrameTarget, frames_in_flight: usize) -> Self {
+        let mut frames = Vec::with_capacity(frames_in_flight);
 
-        for _ in 0..display.frames_in_flight() {
+        for _ in 0..frames_in_flight {
             let swapchain_ready = Semaphore::new(context.clone());
             let rendering_finished = Semaphore::new(context.clone());
             let inflight = Fence::new(context.clone(), true);
@@ -84,27 +155,33 @@ impl Frames {
 
         Self {
             context,
-            display,
+            target,
             frames,
             counter: 0,
         }
     }
 
     pub fn next(&mut self) -> FrameRef<'_> {
-        self.counter = (self.counter + 1) % self.display.frames_in_flight();
+        self.counter = (self.counter + 1) % self.frames.len();
 
         let sync = self.frames.get_mut(self.counter).unwrap();
 
         sync.inflight.wait_and_reset();
         sync.command_pool.reset();
 
-        let (index, _suboptimal) = self.display.acquire_next_image(&sync.swapchain_ready);
+        let index = match &self.target {
+            FrameTarget::Display(display) => {
+                let (index, _suboptimal) = display.acquire_next_image(&sync.swapchain_ready);
+                index
+            }
+            FrameTarget::Offscreen(_) => 0,
+        };
 
         FrameRef {
             frame: sync,
             context: &self.context,
-            display: &self.display,
-            swapchain_index: index,
+            target: &self.target,
+            index,
         }
     }
 }
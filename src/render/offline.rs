@@ -0,0 +1,147 @@
+use super::{
+    frame::Frames,
+    raytracer::{scene::SceneLoadError, Raytracer},
+};
+use crate::{
+    loader::{environment::Environment, SceneData},
+    vulkan::{buffer::Buffer, context::Context, image::Image},
+    world::World,
+};
+use ash::vk;
+use glam::UVec2;
+use std::sync::Arc;
+
+// Renders into an offscreen image on a headless context, so that scenes can be rendered
+// without a window or display server
+pub struct OfflineRenderer {
+    context: Arc<Context>,
+    frames: Frames,
+    readback: Buffer,
+    dims: UVec2,
+
+    raytracer: Raytracer,
+}
+
+impl OfflineRenderer {
+    // The raytracer's output is read back as RGBA32F
+    const PIXEL_SIZE: u64 = 16;
+
+    pub fn new(dims: UVec2) -> Self {
+        let context = Arc::new(Context::headless());
+        let frames = Frames::offscreen(context.clone(), dims);
+
+        let readback = Buffer::new(
+            context.clone(),
+            Self::PIXEL_SIZE * (dims.x * dims.y) as u64,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            gpu_allocator::MemoryLocation::GpuToCpu,
+            "Offscreen Readback Buffer",
+        );
+
+        let raytracer = Raytracer::new(context.clone());
+
+        Self {
+            context,
+            frames,
+            readback,
+            dims,
+
+            raytracer,
+        }
+    }
+
+    pub fn load_scene(&mut self, data: SceneData) -> Result<(), SceneLoadError> {
+        self.raytracer.load_scene(self.context.clone(), data)
+    }
+
+    pub fn load_environment(&mut self, environment: &Environment) {
+        self.raytracer
+            .load_environment(self.context.clone(), environment);
+    }
+
+    // Renders a single frame, which the raytracer accumulates with every previous frame
+    pub fn render(&mut self, world: &World) {
+        let mut frame = self.frames.next();
+        let cmds = frame.allocate_command_list();
+
+        cmds.begin();
+        self.raytracer.run(&cmds, &frame, world);
+
+        // Read back the HDR output rather than the frame's image, so that nothing is clipped
+        if let Some(output) = self.raytracer.output() {
+            let output = &output.image;
+
+            let barrier = [vk::ImageMemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                src_access_mask: vk::AccessFlags2::SHADER_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                dst_access_mask: vk::AccessFlags2::TRANSFER_READ,
+                old_layout: vk::ImageLayout::GENERAL,
+                new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: output.handle,
+                subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
+                ..Default::default()
+            }];
+            cmds.pipeline_barrier(&barrier, &[]);
+
+            let region = vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: vk::Extent3D {
+                    width: self.dims.x,
+                    height: self.dims.y,
+                    depth: 1,
+                },
+            };
+            cmds.copy_to_buffer(output, &self.readback, &[region]);
+
+            // The raytracer expects to find its output where it left it
+            let barrier = [vk::ImageMemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                src_access_mask: vk::AccessFlags2::NONE,
+                dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                dst_access_mask: vk::AccessFlags2::SHADER_READ | vk::AccessFlags2::SHADER_WRITE,
+                old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                new_layout: vk::ImageLayout::GENERAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: output.handle,
+                subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
+                ..Default::default()
+            }];
+            cmds.pipeline_barrier(&barrier, &[]);
+        }
+        cmds.end();
+
+        frame.submit(&[cmds]);
+        self.context.wait_idle();
+    }
+
+    // Returns the radiance accumulated so far as tightly packed linear RGBA pixels
+    pub fn pixels(&self) -> Vec<f32> {
+        let size = (self.dims.x * self.dims.y * 4) as usize;
+        let mut pixels = vec![0.0f32; size];
+        unsafe {
+            let ptr = self.readback.get_ptr().cast::<f32>().as_ptr();
+            ptr.copy_to_nonoverlapping(pixels.as_mut_ptr(), size);
+        }
+
+        pixels
+    }
+}
+
+impl Drop for OfflineRenderer {
+    fn drop(&mut self) {
+        self.context.wait_idle()
+    }
+}
@@ -225,8 +225,8 @@ impl InterfacePainter {
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D::default(),
                 extent: vk::Extent2D {
-                    width: frame.display.dims.x,
-                    height: frame.display.dims.y,
+                    width: frame.dims().x,
+                    height: frame.dims().y,
                 },
             })
             .color_attachments(std::slice::from_ref(&swapchain_attachment))
@@ -242,11 +242,11 @@ impl InterfacePainter {
         cmds.set_viewport(
             0.0,
             0.0,
-            frame.display.dims.x as f32,
-            frame.display.dims.y as f32,
+            frame.dims().x as f32,
+            frame.dims().y as f32,
         );
 
-        let push_constants = frame.display.dims.as_vec2() / frame.display.dpi;
+        let push_constants = frame.dims().as_vec2() / frame.dpi();
         cmds.push_constants(
             &self.pipeline_layout,
             vk::ShaderStageFlags::VERTEX,
@@ -265,7 +265,7 @@ impl InterfacePainter {
 
         let primitives = interface
             .context()
-            .tessellate(output.shapes, frame.display.dpi);
+            .tessellate(output.shapes, frame.dpi());
 
         for primitive in primitives {
             let mesh = match primitive.primitive {
@@ -289,7 +289,7 @@ impl InterfacePainter {
             );
 
             let min = (glam::vec2(primitive.clip_rect.min.x, primitive.clip_rect.min.y)
-                * frame.display.dpi)
+                * frame.dpi())
                 .as_ivec2();
             let offset = vk::Offset2D { x: min.x, y: min.y };
 
@@ -321,7 +321,7 @@ impl InterfacePainter {
             new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
             src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
             dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-            image: frame.display.images[frame.index()].handle,
+            image: frame.image().handle,
             subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
             ..Default::default()
         }];
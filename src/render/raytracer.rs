@@ -1,27 +1,36 @@
-use self::{scene::Scene, shaders::Uniforms};
+use self::{
+    accumulation::Accumulation,
+    environment::EnvironmentMap,
+    scene::{Scene, SceneLoadError},
+    shaders::Uniforms,
+};
 
 use super::frame::FrameRef;
 use crate::{
-    loader::{SceneLoader},
+    loader::{environment::Environment, SceneData},
     vulkan::{
         command::{CommandList},
         context::Context,
         descriptor::{
-            DescriptorBinding, DescriptorBufferWrite, DescriptorImageWrite, DescriptorPool,
-            DescriptorSet, DescriptorSetLayout, DescriptorTLASWrite,
+            DescriptorBinding, DescriptorBufferWrite, DescriptorImageArrayWrite,
+            DescriptorImageWrite, DescriptorPool, DescriptorSet, DescriptorSetLayout,
+            DescriptorTLASWrite,
         },
         image::Image,
         pipeline::{ComputePipeline, PipelineLayout},
         shader::Shader,
     },
-    world::World,
+    world::{RenderStats, World},
 };
 use ash::vk::{self};
 
 use std::{sync::Arc};
 
-mod scene;
-mod shaders;
+pub(super) mod accumulation;
+pub(super) mod environment;
+pub(super) mod scene;
+pub(super) mod shaders;
+pub(super) mod sky;
 mod shader {
     include!(concat!(env!("OUT_DIR"), "/raytracer.comp.rs"));
 }
@@ -37,6 +46,10 @@ pub struct Raytracer {
 
     uniforms: Uniforms,
     scene: Option<Scene>,
+    accumulation: Option<Accumulation>,
+
+    // Kept across scene loads, and black until one is loaded
+    environment: EnvironmentMap,
 }
 
 impl Raytracer {
@@ -68,6 +81,42 @@ impl Raytracer {
                 kind: vk::DescriptorType::STORAGE_BUFFER,
                 stage: vk::ShaderStageFlags::COMPUTE,
             },
+            DescriptorBinding {
+                binding: 4,
+                count: 1,
+                kind: vk::DescriptorType::STORAGE_BUFFER,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
+            DescriptorBinding {
+                binding: 5,
+                count: 1,
+                kind: vk::DescriptorType::STORAGE_BUFFER,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
+            DescriptorBinding {
+                binding: 6,
+                count: Scene::max_textures(&context) as u32,
+                kind: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
+            DescriptorBinding {
+                binding: 7,
+                count: 1,
+                kind: vk::DescriptorType::STORAGE_BUFFER,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
+            DescriptorBinding {
+                binding: 8,
+                count: 1,
+                kind: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
+            DescriptorBinding {
+                binding: 9,
+                count: 1,
+                kind: vk::DescriptorType::STORAGE_BUFFER,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
         ];
 
         let descriptor_layout = DescriptorSetLayout::new(context.clone(), bindings);
@@ -94,6 +143,7 @@ impl Raytracer {
         let descriptor_sets = descriptor_pool.allocate(&context, &descriptor_layout, 3);
 
         let uniforms = Uniforms::new(context.clone());
+        let environment = EnvironmentMap::new(context.clone(), &Environment::black());
 
         Self {
             descriptor_pool,
@@ -104,14 +154,52 @@ impl Raytracer {
             descriptor_sets,
             uniforms,
             scene: None,
+            accumulation: None,
+            environment,
         }
     }
 
-    pub fn run(&mut self, cmds: &CommandList, frame: &FrameRef, world: &World) {
-        if let Some(scene) = SceneLoader::poll() {
-            self.scene = Some(Scene::load(frame.context.clone(), scene));
+    pub fn load_scene(
+        &mut self,
+        context: Arc<Context>,
+        data: SceneData,
+    ) -> Result<(), SceneLoadError> {
+        self.scene = Some(Scene::load(context, data)?);
+
+        // Anything accumulated so far belongs to the old scene
+        if let Some(accumulation) = &mut self.accumulation {
+            accumulation.reset();
+        }
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        match &self.accumulation {
+            Some(accumulation) if self.scene.is_some() => RenderStats {
+                frames: accumulation.frames,
+                samples: accumulation.samples,
+            },
+            _ => RenderStats::default(),
+        }
+    }
+
+    pub fn load_environment(&mut self, context: Arc<Context>, environment: &Environment) {
+        self.environment = EnvironmentMap::new(context, environment);
+
+        if let Some(accumulation) = &mut self.accumulation {
+            accumulation.reset();
         }
+    }
+
+    // The HDR image holding the converged radiance, if anything has been traced yet
+    pub fn output(&self) -> Option<&Accumulation> {
+        self.accumulation
+            .as_ref()
+            .filter(|accumulation| self.scene.is_some() && accumulation.frames > 0)
+    }
 
+    pub fn run(&mut self, cmds: &CommandList, frame: &FrameRef, world: &World) {
         // Only raytrace if there is a scene to trace against!
         if self.scene.is_some() {
             self.raytrace(cmds, frame, world);
@@ -120,17 +208,47 @@ impl Raytracer {
 
     fn raytrace(&mut self, cmds: &CommandList, frame: &FrameRef, world: &World) {
         let scene = self.scene.as_ref().unwrap();
-        let uniforms = self.uniforms.update_uniforms(frame, world);
+
+        // (Re)create the accumulation image whenever the render target changes size
+        if !self
+            .accumulation
+            .as_ref()
+            .is_some_and(|accumulation| accumulation.dims == frame.dims())
+        {
+            self.accumulation = Some(Accumulation::new(frame.context.clone(), frame.dims()));
+        }
+
+        let accumulation = self.accumulation.as_mut().unwrap();
+        accumulation.update(&world.camera, &world.settings);
+
+        let uniforms = self
+            .uniforms
+            .update_uniforms(
+                frame,
+                world,
+                &scene.lights,
+                &self.environment,
+                accumulation.frames,
+            );
 
         let descriptor_set = self.descriptor_sets.get(frame.index()).unwrap();
         descriptor_set.write(
-            &[DescriptorImageWrite {
-                image_view: frame.display.views.get(frame.index()).unwrap(),
-                layout: vk::ImageLayout::GENERAL,
-                binding: 0,
-                sampler: None,
-                image_kind: vk::DescriptorType::STORAGE_IMAGE,
-            }],
+            &[
+                DescriptorImageWrite {
+                    image_view: &accumulation.view,
+                    layout: vk::ImageLayout::GENERAL,
+                    binding: 0,
+                    sampler: None,
+                    image_kind: vk::DescriptorType::STORAGE_IMAGE,
+                },
+                DescriptorImageWrite {
+                    image_view: &self.environment.texture.view,
+                    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    binding: 8,
+                    sampler: Some(self.environment.sampler.handle),
+                    image_kind: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                },
+            ],
             &[
                 DescriptorBufferWrite {
                     buffer_kind: vk::DescriptorType::UNIFORM_BUFFER,
@@ -141,27 +259,64 @@ impl Raytracer {
                 DescriptorBufferWrite {
                     buffer_kind: vk::DescriptorType::STORAGE_BUFFER,
                     buffer: &scene.materials,
-                    range: Scene::MATERIAL_BUFFER_SIZE,
+                    range: vk::WHOLE_SIZE,
                     binding: 3,
                 },
+                DescriptorBufferWrite {
+                    buffer_kind: vk::DescriptorType::STORAGE_BUFFER,
+                    buffer: &scene.lights.buffer,
+                    range: vk::WHOLE_SIZE,
+                    binding: 4,
+                },
+                DescriptorBufferWrite {
+                    buffer_kind: vk::DescriptorType::STORAGE_BUFFER,
+                    buffer: &scene.geometries,
+                    range: vk::WHOLE_SIZE,
+                    binding: 5,
+                },
+                DescriptorBufferWrite {
+                    buffer_kind: vk::DescriptorType::STORAGE_BUFFER,
+                    buffer: &scene.lights.punctual_buffer,
+                    range: vk::WHOLE_SIZE,
+                    binding: 7,
+                },
+                DescriptorBufferWrite {
+                    buffer_kind: vk::DescriptorType::STORAGE_BUFFER,
+                    buffer: &self.environment.cdf,
+                    range: vk::WHOLE_SIZE,
+                    binding: 9,
+                },
             ],
         );
 
+        descriptor_set.write_image_array(DescriptorImageArrayWrite {
+            images: &scene.texture_descriptors,
+            image_kind: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            binding: 6,
+        });
+
         descriptor_set.write_tlas(DescriptorTLASWrite {
             reference: &scene.tlas,
             binding: 2,
         });
 
+        // The previous contents of the accumulation image only matter if they are going to be
+        // blended with, so they can be discarded on the first frame after a reset
+        let accumulation_layout = match accumulation.frames {
+            0 => vk::ImageLayout::UNDEFINED,
+            _ => vk::ImageLayout::GENERAL,
+        };
+
         let image_memory_barriers = [vk::ImageMemoryBarrier2 {
-            src_stage_mask: vk::PipelineStageFlags2::NONE,
-            src_access_mask: vk::AccessFlags2::NONE,
+            src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+            src_access_mask: vk::AccessFlags2::SHADER_WRITE,
             dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
-            dst_access_mask: vk::AccessFlags2::SHADER_WRITE,
-            old_layout: vk::ImageLayout::UNDEFINED,
+            dst_access_mask: vk::AccessFlags2::SHADER_READ | vk::AccessFlags2::SHADER_WRITE,
+            old_layout: accumulation_layout,
             new_layout: vk::ImageLayout::GENERAL,
             src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
             dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-            image: frame.display.images.get(frame.index()).unwrap().handle,
+            image: accumulation.image.handle,
             subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
             ..Default::default()
         }];
@@ -175,18 +330,22 @@ impl Raytracer {
             &[descriptor_set.handle],
         );
 
-        cmds.dispatch(frame.display.dims.x, frame.display.dims.y, 1);
+        cmds.dispatch(frame.dims().x, frame.dims().y, 1);
+
+        accumulation.frames += 1;
+        accumulation.samples += world.settings.samples;
 
+        // Make the new result visible to the display pass
         let image_memory_barriers = [vk::ImageMemoryBarrier2 {
             src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
             src_access_mask: vk::AccessFlags2::SHADER_WRITE,
-            dst_stage_mask: vk::PipelineStageFlags2::NONE,
-            dst_access_mask: vk::AccessFlags2::NONE,
+            dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+            dst_access_mask: vk::AccessFlags2::SHADER_READ,
             old_layout: vk::ImageLayout::GENERAL,
-            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: vk::ImageLayout::GENERAL,
             src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
             dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-            image: frame.display.images.get(frame.index()).unwrap().handle,
+            image: accumulation.image.handle,
             subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
             ..Default::default()
         }];
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use ash::vk;
+use glam::UVec2;
+
+use crate::{
+    vulkan::{
+        context::Context,
+        image::{Image, ImageView},
+    },
+    world::{Camera, RenderSettings, Tonemap},
+};
+
+// A persistent HDR image which holds the running mean of every frame traced since the last reset,
+// so that a still camera converges over time instead of showing a fresh noisy estimate each frame
+pub struct Accumulation {
+    pub image: Image,
+    pub view: ImageView,
+    pub dims: UVec2,
+
+    // Number of frames and samples per pixel averaged into the image so far
+    pub frames: u32,
+    pub samples: u32,
+
+    // The state the current image was accumulated with, a change in either invalidates it
+    camera: Option<Camera>,
+    settings: Option<RenderSettings>,
+}
+
+impl Accumulation {
+    pub const FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+    pub fn new(context: Arc<Context>, dims: UVec2) -> Self {
+        let image = Image::new(
+            context.clone(),
+            dims.extend(1),
+            Self::FORMAT,
+            // Offline renders copy the image out to read it back
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+            "Accumulation Image",
+        );
+
+        let view = ImageView::new(
+            context.clone(),
+            &image,
+            Self::FORMAT,
+            Image::default_subresource(vk::ImageAspectFlags::COLOR),
+        );
+
+        Self {
+            image,
+            view,
+            dims,
+            frames: 0,
+            samples: 0,
+            camera: None,
+            settings: None,
+        }
+    }
+
+    // Discards everything accumulated so far, the next frame will overwrite the image
+    pub fn reset(&mut self) {
+        self.frames = 0;
+        self.samples = 0;
+    }
+
+    // Resets the accumulation if the camera or render settings have changed since the last frame
+    pub fn update(&mut self, camera: &Camera, settings: &RenderSettings) {
+        // Exposure and tonemapping are applied after accumulation, so changing them is free
+        let settings = &RenderSettings {
+            exposure: 1.0,
+            tonemap: Tonemap::default(),
+            ..*settings
+        };
+
+        if self.camera.as_ref() != Some(camera) || self.settings.as_ref() != Some(settings) {
+            self.camera = Some(*camera);
+            self.settings = Some(*settings);
+            self.reset();
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use super::scene::{Scene, Texture};
+use crate::{
+    loader::{environment::Environment, images::GpuImage},
+    vulkan::{buffer::Buffer, command::CommandPool, context::Context, image::Sampler},
+};
+use ash::vk;
+use std::{ptr, sync::Arc};
+
+// The environment map on the GPU, with the CDFs the shader binary searches to importance sample it
+pub struct EnvironmentMap {
+    pub texture: Texture,
+    pub sampler: Sampler,
+    pub cdf: Buffer,
+
+    // Normalises the luminance of a texel into a pdf, zero if there's nothing to sample
+    pub integral: f32,
+}
+
+impl EnvironmentMap {
+    pub fn new(context: Arc<Context>, environment: &Environment) -> Self {
+        let command_pool = CommandPool::new(context.clone(), context.queue_family);
+
+        let image = GpuImage {
+            bytes: bytemuck::cast_slice(&environment.pixels).to_vec(),
+            dims: environment.dims.extend(1),
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            mip_offsets: vec![0],
+        };
+
+        let texture = Scene::upload_textures(&context, &command_pool, vec![image])
+            .pop()
+            .unwrap();
+
+        // Longitude wraps around, but latitude stops at the poles
+        let create_info = vk::SamplerCreateInfo::builder()
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .min_lod(0.0)
+            .max_lod(0.0);
+        let sampler = Sampler::from_create_info(context.clone(), &create_info);
+
+        let cdf = Buffer::new(
+            context.clone(),
+            std::mem::size_of_val(environment.cdf.as_slice()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            "Environment CDF Buffer",
+        );
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                environment.cdf.as_ptr(),
+                cdf.get_ptr().cast::<f32>().as_ptr(),
+                environment.cdf.len(),
+            );
+        }
+
+        Self {
+            texture,
+            sampler,
+            cdf,
+            integral: environment.integral,
+        }
+    }
+}
@@ -1,82 +1,310 @@
 use std::{ptr, sync::Arc};
 
 use crate::{
-    loader::{images::GpuImage, objects::GpuObject, SceneData},
+    loader::{
+        images::{GpuImage, GpuTexture},
+        lights::{self, LightKind},
+        objects::{GpuInstance, GpuMaterial, GpuMesh, TextureSlot},
+        SceneData,
+    },
     vulkan::{
         buffer::Buffer,
         command::CommandPool,
         context::Context,
-        image::Image,
+        image::{Image, ImageView, Sampler},
         rt::{AccelerationStructure, GeometryDescription, GeometryInstance},
         sync::Fence,
     },
 };
 use ash::vk::{self, BufferImageCopy};
 use glam::Vec3Swizzles;
+use gltf::material::AlphaMode;
+use thiserror::Error;
 
 pub struct Texture {
     image: Image,
+    pub view: ImageView,
     dims: glam::UVec2,
     format: vk::Format,
 }
 
-pub struct Mesh {
+// The buffers of one of a mesh's primitives, built into its BLAS as a separate geometry
+pub struct Geometry {
     vertices: Buffer,
     indices: Buffer,
+    attributes: Buffer,
+}
+
+pub struct Mesh {
+    geometries: Vec<Geometry>,
     blas: AccelerationStructure,
+
+    // Index of the mesh's first geometry in the geometry buffer, used as its instances' custom index
+    first_geometry: u32,
+}
+
+// The shading attributes of a single vertex, as read by the shader
+#[repr(C)]
+pub struct Vertex {
+    normal: glam::Vec3A,
+    tangent: glam::Vec4,
+
+    // The first set of texture coordinates in xy, and the second in zw
+    tex_coords: glam::Vec4,
+}
+
+// Device addresses of a geometry's index and attribute buffers, along with its material. The
+// shader finds the geometry that was hit by adding its geometry index within the BLAS to the
+// custom index of the instance, which holds the offset of the mesh's first geometry
+#[repr(C)]
+pub struct GeometryData {
+    attributes: vk::DeviceAddress,
+    indices: vk::DeviceAddress,
+    material: u32,
+}
+
+// An index into the shader's texture array and the set of texture coordinates to sample it
+// with. The index is -1 when the material doesn't use the texture
+#[repr(C)]
+pub struct TextureRef {
+    index: i32,
+    tex_coord: u32,
+}
+
+impl TextureRef {
+    fn new(slot: Option<TextureSlot>) -> Self {
+        match slot {
+            Some(slot) => Self {
+                index: slot.texture as i32,
+                tex_coord: slot.tex_coord,
+            },
+            None => Self {
+                index: -1,
+                tex_coord: 0,
+            },
+        }
+    }
 }
 
 #[repr(C)]
 pub struct Material {
-    base_color: glam::Vec3A,
+    base_color: glam::Vec4,
     emissive: glam::Vec3A,
     roughness: f32,
     metallic: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+
+    base_color_texture: TextureRef,
+    metallic_roughness_texture: TextureRef,
+    normal_texture: TextureRef,
+    emissive_texture: TextureRef,
+    occlusion_texture: TextureRef,
+
+    // 0 for opaque materials, 1 for masked ones and 2 for blended ones
+    alpha_mode: u32,
+    alpha_cutoff: f32,
+}
+
+// A world-space emissive triangle, as sampled by next event estimation in the shader
+#[repr(C)]
+pub struct EmissiveTriangle {
+    v0: glam::Vec3A,
+    v1: glam::Vec3A,
+    v2: glam::Vec3A,
+    emissive: glam::Vec3A,
+
+    // Probability of picking this triangle or any before it, triangles are picked by their power
+    cdf: f32,
+
+    // The geometry and triangle this was taken from, so the shader can sample its emissive texture
+    geometry: u32,
+    primitive: u32,
+}
+
+// A light from KHR_lights_punctual, which the shader samples directly as it can never be hit
+#[repr(C)]
+pub struct PunctualLight {
+    position: glam::Vec3A,
+    direction: glam::Vec3A,
+    // The colour already multiplied by the intensity
+    color: glam::Vec3A,
+
+    // 0 for directional lights, 1 for point lights and 2 for spot lights
+    kind: u32,
+    // Distance at which the light's influence reaches zero, or zero if it's unlimited
+    range: f32,
+
+    // Maps the cosine of the angle from a spot light's axis onto its falloff
+    angle_scale: f32,
+    angle_offset: f32,
+}
+
+pub struct Lights {
+    pub buffer: Buffer,
+    pub count: u32,
+
+    // Sum of the luminance times area of every emissive triangle
+    pub total_power: f32,
+
+    pub punctual_buffer: Buffer,
+    pub punctual_count: u32,
+}
+
+#[derive(Error, Debug)]
+pub enum SceneLoadError {
+    #[error("The {buffer} needs {size} bytes, but the device's storage buffers are limited to {limit} bytes")]
+    BufferTooLarge {
+        buffer: &'static str,
+        size: u64,
+        limit: u32,
+    },
+    #[error("The scene has {0} geometries, but instance custom indices can only address {max}", max = Scene::MAX_GEOMETRIES)]
+    TooManyGeometries(usize),
+    #[error("The scene has {count} textures, but the device can only bind {limit}")]
+    TooManyTextures { count: usize, limit: usize },
 }
 
 pub struct Scene {
     pub textures: Vec<Texture>,
+    pub samplers: Vec<Sampler>,
+
+    // One combined image sampler for each glTF texture, indexed by the materials
+    pub texture_descriptors: Vec<vk::DescriptorImageInfo>,
+
     pub meshes: Vec<Mesh>,
+    pub geometries: Buffer,
     pub materials: Buffer,
+    pub lights: Lights,
 
     pub tlas: AccelerationStructure,
 }
 
 impl Scene {
-	pub const MATERIAL_BUFFER_SIZE: u64 = (std::mem::size_of::<Material>() * 4096) as u64;
+	// Upper bound on the size of the texture array, which keeps the descriptor pool small
+	pub const MAX_TEXTURES: usize = 1024;
 
+    // Instance custom indices, which hold the offset of a mesh's first geometry, are 24 bits
+    pub const MAX_GEOMETRIES: usize = 1 << 24;
+
+    pub fn load(context: Arc<Context>, data: SceneData) -> Result<Self, SceneLoadError> {
+        Self::check_limits(&context, &data)?;
 
-    pub fn load(context: Arc<Context>, data: SceneData) -> Self {
         let command_pool = CommandPool::new(context.clone(), context.queue_family);
-        let textures = Self::upload_textures(&context, &command_pool, data.images);
-		let meshes = Self::build_meshes(&context, &command_pool, &data.objects);
-		let materials = Self::upload_materials(&context, &data.objects);
+		let meshes = Self::build_meshes(&context, &command_pool, &data.meshes, &data.materials);
+		let geometries = Self::upload_geometries(&context, &data.meshes, &meshes);
+		let materials = Self::upload_materials(&context, &data.materials);
+		let lights = Self::upload_lights(&context, &data, &meshes);
 
-		let tlas = Self::build_tlas(&context, &command_pool, &data.objects, &meshes);
+		let tlas = Self::build_tlas(&context, &command_pool, &data.instances, &meshes);
 
-		Self {
+        // The images are moved into the textures, so these are uploaded last
+        let textures = Self::upload_textures(&context, &command_pool, data.images);
+        let (samplers, texture_descriptors) =
+            Self::create_samplers(&context, &data.textures, &textures);
+
+		Ok(Self {
 			textures,
+			samplers,
+			texture_descriptors,
 			meshes,
+			geometries,
 			materials,
+			lights,
 			tlas
-		}
+		})
 	}
 
-    fn upload_textures(
+    // The size of the texture array. Every element is a combined image sampler, so it counts
+    // against both the sampler and sampled image limits, as does the environment map
+    pub fn max_textures(context: &Context) -> usize {
+        let limits = context.limits();
+        let limit = [
+            limits.max_per_stage_descriptor_samplers,
+            limits.max_per_stage_descriptor_sampled_images,
+            limits.max_descriptor_set_samplers,
+            limits.max_descriptor_set_sampled_images,
+        ]
+        .into_iter()
+        .min()
+        .unwrap_or(0);
+
+        (limit.saturating_sub(1) as usize).min(Self::MAX_TEXTURES)
+    }
+
+    // Checks the scene fits in the buffers and texture array the shader reads, before anything is
+    // uploaded
+    fn check_limits(context: &Context, data: &SceneData) -> Result<(), SceneLoadError> {
+        let limit = Self::max_textures(context);
+        if data.textures.len() > limit {
+            return Err(SceneLoadError::TooManyTextures {
+                count: data.textures.len(),
+                limit,
+            });
+        }
+
+        let geometries = data
+            .meshes
+            .iter()
+            .map(|mesh| mesh.primitives.len())
+            .sum::<usize>();
+
+        if geometries > Self::MAX_GEOMETRIES {
+            return Err(SceneLoadError::TooManyGeometries(geometries));
+        }
+
+        // Every triangle of an emissive primitive becomes a light for each instance of its mesh
+        let emissive_triangles = data
+            .instances
+            .iter()
+            .flat_map(|instance| &data.meshes[instance.mesh].primitives)
+            .filter(|primitive| data.materials[primitive.material].emissive != glam::Vec3A::ZERO)
+            .map(|primitive| primitive.indices.len() / 3)
+            .sum::<usize>();
+
+        let buffers = [
+            ("material buffer", data.materials.len() * std::mem::size_of::<Material>()),
+            ("geometry buffer", geometries * std::mem::size_of::<GeometryData>()),
+            ("light buffer", emissive_triangles * std::mem::size_of::<EmissiveTriangle>()),
+            ("punctual light buffer", data.lights.len() * std::mem::size_of::<PunctualLight>()),
+        ];
+
+        let limit = context.limits().max_storage_buffer_range;
+        for (buffer, size) in buffers {
+            if size as u64 > limit as u64 {
+                return Err(SceneLoadError::BufferTooLarge {
+                    buffer,
+                    size: size as u64,
+                    limit,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn upload_textures(
         context: &Arc<Context>,
         command_pool: &CommandPool,
         images: Vec<GpuImage>,
     ) -> Vec<Texture> {
         let mut textures = Vec::with_capacity(images.len());
         for (index, image) in images.into_iter().enumerate() {
-            let texture = Image::new(
+            let mip_levels = image.mip_offsets.len() as u32;
+            let texture = Image::with_mip_levels(
                 context.clone(),
                 image.dims,
+                mip_levels,
                 image.format,
                 vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
                 &format!("Scene Texture {}", index),
             );
 
+            let subresource_range = vk::ImageSubresourceRange {
+                level_count: mip_levels,
+                ..Image::default_subresource(vk::ImageAspectFlags::COLOR)
+            };
+
             let staging = Buffer::new(
                 context.clone(),
                 image.bytes.len() as u64,
@@ -103,30 +331,36 @@ impl Scene {
                 src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
                 dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
                 image: texture.handle,
-                subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
+                subresource_range,
                 ..Default::default()
             }];
 
             cmds.pipeline_barrier(&barrier, &[]);
 
-            let copy = BufferImageCopy {
-                buffer_offset: 0,
-                buffer_row_length: 0,
-                buffer_image_height: 0,
-                image_subresource: vk::ImageSubresourceLayers {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    mip_level: 0,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                },
-                image_offset: vk::Offset3D::default(),
-                image_extent: vk::Extent3D {
-                    width: image.dims.x,
-                    height: image.dims.y,
-                    depth: image.dims.z,
-                },
-            };
-            cmds.copy_to_image(&staging, &texture, &[copy]);
+            // Copy each level of the mip chain from its offset in the staging buffer
+            let copies = image
+                .mip_offsets
+                .iter()
+                .enumerate()
+                .map(|(level, &offset)| BufferImageCopy {
+                    buffer_offset: offset as u64,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: level as u32,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D::default(),
+                    image_extent: vk::Extent3D {
+                        width: (image.dims.x >> level).max(1),
+                        height: (image.dims.y >> level).max(1),
+                        depth: 1,
+                    },
+                })
+                .collect::<Vec<BufferImageCopy>>();
+            cmds.copy_to_image(&staging, &texture, &copies);
 
             let barrier = [vk::ImageMemoryBarrier2 {
                 src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
@@ -138,7 +372,7 @@ impl Scene {
                 src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
                 dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
                 image: texture.handle,
-                subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
+                subresource_range,
                 ..Default::default()
             }];
 
@@ -147,8 +381,12 @@ impl Scene {
 
             context.submit(&[cmds], None, None, Some(&fence));
             fence.wait_and_reset();
+
+            let view = ImageView::new(context.clone(), &texture, image.format, subresource_range);
+
             textures.push(Texture {
                 image: texture,
+                view,
                 dims: image.dims.xy(),
                 format: image.format,
             });
@@ -156,160 +394,374 @@ impl Scene {
         textures
     }
 
-    fn build_meshes(context: &Arc<Context>, command_pool: &CommandPool, objects: &Vec<GpuObject>) -> Vec<Mesh> {
-        let mut descs = Vec::new();
-        let mut buffer_pairs = Vec::new();
-        for (_index, object) in objects.iter().enumerate() {
-            // Create staging buffers that are accesible by the CPU
-            let indices_staging = Buffer::new(
-                context.clone(),
-                (object.indices.len() * std::mem::size_of::<u32>()) as u64,
-                vk::BufferUsageFlags::TRANSFER_SRC,
-                gpu_allocator::MemoryLocation::CpuToGpu,
-                "Mesh Indices Staging Buffer",
-            );
-
-            let vertices_staging = Buffer::new(
-                context.clone(),
-                (object.vertices.len() * std::mem::size_of::<f32>()) as u64,
-                vk::BufferUsageFlags::TRANSFER_SRC,
-                gpu_allocator::MemoryLocation::CpuToGpu,
-                "Mesh Vertices Staging Buffer",
-            );
+    // Creates a sampler for each glTF texture, and pairs it with the texture's image
+    fn create_samplers(
+        context: &Arc<Context>,
+        gltf_textures: &[GpuTexture],
+        textures: &[Texture],
+    ) -> (Vec<Sampler>, Vec<vk::DescriptorImageInfo>) {
+        let samplers = gltf_textures
+            .iter()
+            .map(|texture| {
+                let create_info = vk::SamplerCreateInfo::builder()
+                    .address_mode_u(texture.address_modes[0])
+                    .address_mode_v(texture.address_modes[1])
+                    .address_mode_w(vk::SamplerAddressMode::REPEAT)
+                    .mag_filter(texture.mag_filter)
+                    .min_filter(texture.min_filter)
+                    .mipmap_mode(texture.mipmap_mode)
+                    .min_lod(0.0)
+                    .max_lod(vk::LOD_CLAMP_NONE);
+
+                Sampler::from_create_info(context.clone(), &create_info)
+            })
+            .collect::<Vec<Sampler>>();
+
+        let descriptors = gltf_textures
+            .iter()
+            .zip(&samplers)
+            .map(|(texture, sampler)| vk::DescriptorImageInfo {
+                sampler: sampler.handle,
+                image_view: textures[texture.image].view.handle,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            })
+            .collect::<Vec<vk::DescriptorImageInfo>>();
 
-            // Create buffers that are stored on GPU VRAM
-            let vertices = Buffer::new(
-                context.clone(),
-                (object.vertices.len() * std::mem::size_of::<f32>()) as u64,
-                vk::BufferUsageFlags::TRANSFER_DST
-                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-                gpu_allocator::MemoryLocation::GpuOnly,
-                "Vertex Buffer",
-            );
+        (samplers, descriptors)
+    }
 
-            let indices = Buffer::new(
-                context.clone(),
-                (object.indices.len() * std::mem::size_of::<u32>()) as u64,
-                vk::BufferUsageFlags::TRANSFER_DST
+    fn build_meshes(
+        context: &Arc<Context>,
+        command_pool: &CommandPool,
+        meshes: &[GpuMesh],
+        materials: &[GpuMaterial],
+    ) -> Vec<Mesh> {
+        let mut descs = Vec::new();
+        let mut geometries = Vec::new();
+        for mesh in meshes {
+            let mut mesh_descs = Vec::new();
+            let mut mesh_geometries = Vec::new();
+
+            for primitive in &mesh.primitives {
+                // Positions and indices are read by the BLAS builds, and indices and attributes
+                // are also read by the shader through their device addresses
+                let usage = vk::BufferUsageFlags::STORAGE_BUFFER
                     | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-                gpu_allocator::MemoryLocation::GpuOnly,
-                "Index Buffer",
-            );
-
-            let fence = Fence::new(context.clone(), false);
+                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR;
+
+                let vertices = Self::upload_to_gpu(
+                    context,
+                    command_pool,
+                    &primitive.vertices,
+                    usage,
+                    "Vertex Buffer",
+                );
 
-            // Copy the data from the buffers into the staging buffer
-            unsafe {
-                ptr::copy_nonoverlapping(
-                    object.indices.as_ptr(),
-                    indices_staging.get_ptr().cast::<u32>().as_ptr(),
-                    object.indices.len(),
+                let indices = Self::upload_to_gpu(
+                    context,
+                    command_pool,
+                    &primitive.indices,
+                    usage,
+                    "Index Buffer",
                 );
-                ptr::copy_nonoverlapping(
-                    object.vertices.as_ptr(),
-                    vertices_staging.get_ptr().cast::<f32>().as_ptr(),
-                    object.vertices.len(),
+
+                let attributes = (0..primitive.normals.len())
+                    .map(|index| {
+                        let uv0 = primitive.tex_coords[0][index];
+                        let uv1 = primitive.tex_coords[1][index];
+                        Vertex {
+                            normal: primitive.normals[index].into(),
+                            tangent: primitive.tangents[index],
+                            tex_coords: glam::vec4(uv0.x, uv0.y, uv1.x, uv1.y),
+                        }
+                    })
+                    .collect::<Vec<Vertex>>();
+
+                let attributes = Self::upload_to_gpu(
+                    context,
+                    command_pool,
+                    &attributes,
+                    usage,
+                    "Attribute Buffer",
                 );
+
+                mesh_descs.push(GeometryDescription {
+                    vertices: vertices.get_addr(),
+                    indices: indices.get_addr(),
+                    max_vertex: (primitive.vertices.len() / 3 - 1) as u32,
+                    primitives: (primitive.indices.len() / 3) as u32,
+                    // Anything else has to pass the alpha test in the shader to be hit
+                    opaque: materials[primitive.material].alpha_mode == AlphaMode::Opaque,
+                });
+
+                mesh_geometries.push(Geometry {
+                    vertices,
+                    indices,
+                    attributes,
+                });
             }
 
-            let cmds = command_pool.allocate();
-            let vertices_region = vk::BufferCopy {
-                src_offset: 0,
-                dst_offset: 0,
-                size: (object.vertices.len() * std::mem::size_of::<f32>()) as u64,
-            };
+            descs.push(mesh_descs);
+            geometries.push(mesh_geometries);
+        }
 
-            let indices_region = vk::BufferCopy {
-                src_offset: 0,
-                dst_offset: 0,
-                size: (object.indices.len() * std::mem::size_of::<u32>()) as u64,
-            };
+        let blasses = AccelerationStructure::build_bottom_levels(context.clone(), &descs);
 
-            cmds.begin();
+        let mut first_geometry = 0;
+        blasses
+            .into_iter()
+            .zip(geometries)
+            .map(|(blas, geometries)| {
+                let mesh = Mesh {
+                    first_geometry,
+                    geometries,
+                    blas,
+                };
+                first_geometry += mesh.geometries.len() as u32;
+                mesh
+            })
+            .collect::<Vec<Mesh>>()
+    }
 
-            cmds.copy_buffer(&vertices_staging, &vertices, &[vertices_region]);
-            cmds.copy_buffer(&indices_staging, &indices, &[indices_region]);
+    // Copies `data` into a new buffer in GPU memory, through a staging buffer accessible by the CPU
+    fn upload_to_gpu<T>(
+        context: &Arc<Context>,
+        command_pool: &CommandPool,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        name: &str,
+    ) -> Buffer {
+        let size = std::mem::size_of_val(data) as u64;
 
-            cmds.end();
+        let staging = Buffer::new(
+            context.clone(),
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            &format!("{} Staging Buffer", name),
+        );
 
-            context.submit(&[cmds], None, None, Some(&fence));
-            fence.wait_and_reset();
+        let buffer = Buffer::new(
+            context.clone(),
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            gpu_allocator::MemoryLocation::GpuOnly,
+            name,
+        );
 
-            let desc = GeometryDescription {
-                vertices: vertices.get_addr(),
-                indices: indices.get_addr(),
-                max_vertex: (object.vertices.len() - 1) as u32,
-                primitives: object.indices.len().div_ceil(3) as u32,
-            };
+        unsafe {
+            ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                staging.get_ptr().cast::<T>().as_ptr(),
+                data.len(),
+            );
+        }
+
+        let fence = Fence::new(context.clone(), false);
+        let cmds = command_pool.allocate();
+        let region = vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size,
+        };
 
-            descs.push(desc);
+        cmds.begin();
+        cmds.copy_buffer(&staging, &buffer, &[region]);
+        cmds.end();
 
-            buffer_pairs.push((vertices, indices));
+        context.submit(&[cmds], None, None, Some(&fence));
+        fence.wait_and_reset();
+
+        buffer
+    }
+
+    // Writes the device addresses and material of every mesh's geometries, one mesh after another
+    fn upload_geometries(context: &Arc<Context>, data: &[GpuMesh], meshes: &[Mesh]) -> Buffer {
+        let geometries = data
+            .iter()
+            .zip(meshes)
+            .flat_map(|(data, mesh)| data.primitives.iter().zip(&mesh.geometries))
+            .map(|(primitive, geometry)| GeometryData {
+                attributes: geometry.attributes.get_addr(),
+                indices: geometry.indices.get_addr(),
+                material: primitive.material as u32,
+            })
+            .collect::<Vec<GeometryData>>();
+
+        let buffer = Buffer::new(
+            context.clone(),
+            (geometries.len().max(1) * std::mem::size_of::<GeometryData>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            "Geometry Buffer",
+        );
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                geometries.as_ptr(),
+                buffer.get_ptr().cast::<GeometryData>().as_ptr(),
+                geometries.len(),
+            );
         }
 
-        let blasses = AccelerationStructure::build_bottom_levels(context.clone(), &descs);
+        buffer
+    }
 
-        let meshes = blasses
-            .into_iter()
-            .zip(buffer_pairs)
-            .map(|(blas, (vertices, indices))| Mesh {
-                vertices,
-                indices,
-                blas,
+    fn upload_materials(context: &Arc<Context>, materials: &[GpuMaterial]) -> Buffer {
+        let materials = materials
+            .iter()
+            .map(|material| Material {
+                base_color: material.base_color.extend(material.alpha),
+                emissive: material.emissive,
+                roughness: material.roughness,
+                metallic: material.metallic,
+                normal_scale: material.normal_scale,
+                occlusion_strength: material.occlusion_strength,
+
+                base_color_texture: TextureRef::new(material.base_color_texture),
+                metallic_roughness_texture: TextureRef::new(material.metallic_roughness_texture),
+                normal_texture: TextureRef::new(material.normal_texture),
+                emissive_texture: TextureRef::new(material.emissive_texture),
+                occlusion_texture: TextureRef::new(material.occlusion_texture),
+
+                alpha_mode: match material.alpha_mode {
+                    AlphaMode::Opaque => 0,
+                    AlphaMode::Mask => 1,
+                    AlphaMode::Blend => 2,
+                },
+                alpha_cutoff: material.alpha_cutoff,
             })
-            .collect::<Vec<Mesh>>();
+            .collect::<Vec<Material>>();
+
+        // Sized to fit the scene, with room for at least one material as buffers can't be empty
+        let buffer = Buffer::new(
+            context.clone(),
+            (materials.len().max(1) * std::mem::size_of::<Material>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            "Material Buffer",
+        );
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                materials.as_ptr(),
+                buffer.get_ptr().cast::<Material>().as_ptr(),
+                materials.len(),
+            );
+        }
 
-		meshes
+        buffer
     }
 
-	fn upload_materials(context: &Arc<Context>, objects: &Vec<GpuObject>) -> Buffer {
+    fn upload_lights(
+        context: &Arc<Context>,
+        data: &SceneData,
+        meshes: &[Mesh],
+    ) -> Lights {
+        // Every instance of an emissive primitive is a separate set of lights, placed in world space
+        let (triangles, total_power) =
+            lights::emissive_triangles(&data.meshes, &data.instances, &data.materials);
+        let triangles = triangles
+            .into_iter()
+            .map(|triangle| {
+                let [v0, v1, v2] = triangle.vertices;
+                EmissiveTriangle {
+                    v0,
+                    v1,
+                    v2,
+                    emissive: triangle.emissive,
+                    cdf: triangle.cdf,
+                    geometry: meshes[triangle.mesh].first_geometry + triangle.geometry as u32,
+                    primitive: triangle.primitive,
+                }
+            })
+            .collect::<Vec<EmissiveTriangle>>();
 
-		let material_buffer = Buffer::new(
+        // Buffers can't be empty, so there is always room for at least one light
+        let buffer = Buffer::new(
             context.clone(),
-            Self::MATERIAL_BUFFER_SIZE,
-            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            (triangles.len().max(1) * std::mem::size_of::<EmissiveTriangle>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
             gpu_allocator::MemoryLocation::CpuToGpu,
-            &format!("Material Buffer"),
+            "Light Buffer",
         );
 
-		for (index, object) in objects.iter().enumerate() {
-			let ptr = unsafe {
-                material_buffer
-                    .get_ptr()
-                    .cast::<Material>()
-                    .as_ptr()
-                    .offset(index as isize)
-            };
+        unsafe {
+            ptr::copy_nonoverlapping(
+                triangles.as_ptr(),
+                buffer.get_ptr().cast::<EmissiveTriangle>().as_ptr(),
+                triangles.len(),
+            );
+        }
 
-            let material = Material {
-                base_color: object.base_color,
-                emissive: object.emissive,
-                roughness: object.roughness,
-                metallic: object.metallic,
-            
-			};
-            unsafe { ptr.write(material) };
-		}
-
-		material_buffer
-	}
+        log::info!("Found {} emissive triangles", triangles.len());
+
+        let punctual_lights = data
+            .lights
+            .iter()
+            .map(|light| {
+                // The spot light falloff from the KHR_lights_punctual specification, with a
+                // cone that never cuts off so point and directional lights are unaffected
+                let (angle_scale, angle_offset) = match light.kind {
+                    LightKind::Spot => {
+                        let cos_inner = light.inner_cone_angle.cos();
+                        let cos_outer = light.outer_cone_angle.cos();
+                        let scale = 1.0 / (cos_inner - cos_outer).max(0.001);
+                        (scale, -cos_outer * scale)
+                    }
+                    _ => (0.0, 1.0),
+                };
+
+                PunctualLight {
+                    position: light.position,
+                    direction: light.direction,
+                    color: light.color * light.intensity,
+                    kind: light.kind as u32,
+                    range: light.range.unwrap_or(0.0),
+                    angle_scale,
+                    angle_offset,
+                }
+            })
+            .collect::<Vec<_>>();
 
-	fn build_tlas(context: &Arc<Context>, _command_pool: &CommandPool, objects: &Vec<GpuObject>, meshes: &[Mesh]) -> AccelerationStructure {
-		let mut instances = Vec::with_capacity(objects.len());
+        let punctual_buffer = Buffer::new(
+            context.clone(),
+            (punctual_lights.len().max(1) * std::mem::size_of::<PunctualLight>()) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            "Punctual Light Buffer",
+        );
 
-        for (index, object) in objects.iter().enumerate() {
-			let instance = GeometryInstance {
-                transform: object.transform,
-                blas: meshes[index].blas.get_addr(),
-                index: index as u32,
-            };
+        unsafe {
+            ptr::copy_nonoverlapping(
+                punctual_lights.as_ptr(),
+                punctual_buffer.get_ptr().cast::<PunctualLight>().as_ptr(),
+                punctual_lights.len(),
+            );
+        }
 
-            instances.push(instance)
+        Lights {
+            buffer,
+            count: triangles.len() as u32,
+            total_power,
+            punctual_buffer,
+            punctual_count: punctual_lights.len() as u32,
         }
-		
-		let tlas = AccelerationStructure::build_top_level(context.clone(), &instances);
-		tlas
+    }
+
+    // Places each instance's mesh in the world, sharing one BLAS between all instances of a mesh.
+    // The custom index is the offset of the mesh's geometries, which the shader uses to find the
+    // material and buffers of the geometry that was hit
+	fn build_tlas(context: &Arc<Context>, _command_pool: &CommandPool, instances: &[GpuInstance], meshes: &[Mesh]) -> AccelerationStructure {
+		let instances = instances
+            .iter()
+            .map(|instance| GeometryInstance {
+                transform: instance.transform,
+                blas: meshes[instance.mesh].blas.get_addr(),
+                index: meshes[instance.mesh].first_geometry,
+            })
+            .collect::<Vec<GeometryInstance>>();
+
+		AccelerationStructure::build_top_level(context.clone(), &instances)
 	}
 }
@@ -3,31 +3,117 @@ use std::sync::Arc;
 use ash::vk;
 
 use crate::{
-    render::frame::FrameRef,
+    render::{
+        frame::FrameRef,
+        raytracer::{environment::EnvironmentMap, scene::Lights, sky::SkyModel},
+    },
     vulkan::{buffer::Buffer, context::Context},
-    world::World,
+    world::{Projection, World},
 };
 
 #[repr(C)]
-struct ShaderUniforms {
-    seed: u32,
-    samples: u32,
-    bounces: u32,
-    mode: u32,
+pub struct ShaderUniforms {
+    pub seed: u32,
+    pub samples: u32,
+    pub bounces: u32,
+    pub mode: u32,
 
-    focal_length: f32,
-    aperture: f32,
-    exposure: f32,
-    time: f32,
+    pub focal_length: f32,
+    pub aperture: f32,
+    pub exposure: f32,
+    pub time: f32,
+
+    // Number of frames already in the accumulation image
+    pub frame: u32,
+
+    // Emissive triangles available for next event estimation, and their summed power
+    pub light_count: u32,
+    pub light_power: f32,
+
+    // Angle between the rays of neighbouring pixels, which is how quickly ray cones widen
+    pub spread_angle: f32,
+
+    // Lights from KHR_lights_punctual, which are picked uniformly
+    pub punctual_light_count: u32,
+
+    // Non-zero if rays leave the camera in parallel, from its near plane
+    pub orthographic: u32,
+
+    // Scales the environment's radiance, and rotates it around the y axis in radians
+    pub environment_intensity: f32,
+    pub environment_rotation: f32,
+    // Normalises the environment's importance sampling pdf, zero if it's black
+    pub environment_integral: f32,
+
+    // Non-zero if the procedural sky surrounds the scene in place of the environment map
+    pub sky: u32,
+
+    // Number of blades shaping the aperture into a polygon, or zero for a circular one
+    pub aperture_blades: u32,
 
     // Camera position
-    pos: glam::Vec3A,
+    pub pos: glam::Vec3A,
 
     // View matrix
-    inv_view: glam::Mat4,
+    pub inv_view: glam::Mat4,
 
     // Projection matrix
-    inv_proj: glam::Mat4,
+    pub inv_proj: glam::Mat4,
+
+    pub sky_model: SkyModel,
+}
+
+impl ShaderUniforms {
+    pub fn new(world: &World, dims: glam::UVec2) -> Self {
+        let seed = rand::random();
+
+        // Parallel rays don't spread, their cones keep the width of a pixel instead
+        let spread_angle = match world.settings.projection {
+            Projection::Perspective => {
+                let fov = f32::to_radians(world.settings.fov);
+                (2.0 * (fov * 0.5).tan() / dims.y as f32).atan()
+            }
+            Projection::Orthographic { .. } => 0.0,
+        };
+
+        ShaderUniforms {
+            seed,
+            samples: world.settings.samples,
+            bounces: world.settings.bounces,
+            mode: 0,
+
+            focal_length: world.settings.focal_length,
+            aperture: world.settings.aperture,
+            exposure: world.settings.exposure,
+            time: 0.0,
+
+            frame: 0,
+
+            light_count: 0,
+            light_power: 0.0,
+
+            spread_angle,
+
+            punctual_light_count: 0,
+            orthographic: matches!(world.settings.projection, Projection::Orthographic { .. })
+                as u32,
+
+            environment_intensity: world.settings.environment_intensity,
+            environment_rotation: world.settings.environment_rotation.to_radians(),
+            environment_integral: 0.0,
+
+            sky: world.settings.sky.enabled as u32,
+
+            aperture_blades: world.settings.aperture_blades,
+
+            pos: world.camera.position.into(),
+
+            inv_view: world.view().inverse(),
+            inv_proj: world.projection(dims).inverse(),
+
+            sky_model: SkyModel::new(&world.settings.sky),
+        }
+    }
 }
 
 pub struct Uniforms {
@@ -52,44 +138,28 @@ impl Uniforms {
         Self { buffers }
     }
 
-    pub fn update_uniforms(&mut self, frame: &FrameRef, world: &World) -> &Buffer {
+    pub fn update_uniforms(
+        &mut self,
+        frame: &FrameRef,
+        world: &World,
+        lights: &Lights,
+        environment: &EnvironmentMap,
+        accumulated: u32,
+    ) -> &Buffer {
         let buffer = &self.buffers[frame.index()];
         let ptr = buffer.get_ptr().cast::<ShaderUniforms>().as_ptr();
 
-        let aspect = frame.display.dims.as_vec2();
-        let aspect_ratio = aspect.x / aspect.y;
-
-        let seed = rand::random();
-
-        let forward = world.camera.rotation * glam::vec3(0.0, 0.0, 1.0);
-        let up = world.camera.rotation * glam::vec3(0.0, 1.0, 0.0);
-
-        unsafe {
-            ptr.write(ShaderUniforms {
-                seed,
-                samples: world.settings.samples,
-                bounces: world.settings.bounces,
-                mode: 0,
-
-                focal_length: world.settings.focal_length,
-                aperture: world.settings.aperture,
-                exposure: world.settings.exposure,
-                time: 0.0,
-
-                pos: world.camera.position.into(),
-
-                inv_view: glam::Mat4::look_to_lh(world.camera.position.into(), forward, up)
-                    .inverse(),
-                inv_proj: glam::Mat4::perspective_lh(
-                    f32::to_radians(world.settings.fov),
-                    aspect_ratio,
-                    world.settings.near,
-                    world.settings.far,
-                )
-                .inverse(),
-            })
+        let uniforms = ShaderUniforms {
+            frame: accumulated,
+            light_count: lights.count,
+            light_power: lights.total_power,
+            punctual_light_count: lights.punctual_count,
+            environment_integral: environment.integral,
+            ..ShaderUniforms::new(world, frame.dims())
         };
 
+        unsafe { ptr.write(uniforms) };
+
         buffer
     }
 }
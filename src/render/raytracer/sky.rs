@@ -0,0 +1,127 @@
+use crate::world::Sky;
+use glam::{Vec3, Vec4};
+use std::f32::consts::FRAC_PI_2;
+
+// The parameters of the Preetham daylight model for a given sun position and turbidity,
+// evaluated by shaders/sky.glsl for each direction.
+// Source : https://courses.cs.duke.edu/cps124/spring08/assign/07_papers/p91-preetham.pdf
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct SkyModel {
+    // The direction towards the sun, and the cosine of its angular radius in w
+    pub sun_direction: Vec4,
+    // Radiance of the sun's disk after passing through the atmosphere, zero below the horizon
+    pub sun_radiance: Vec4,
+
+    // The zenith's x and y chromaticity and luminance, divided by the Perez function there
+    pub zenith: Vec4,
+    // The Perez distribution's A to E coefficients, for x, y and luminance in turn
+    pub perez: [Vec4; 5],
+}
+
+impl SkyModel {
+    // The sun's angular radius, in radians
+    const SUN_RADIUS: f32 = 0.00465;
+
+    // Luminance of the sun outside the atmosphere, in the kcd/m^2 the model's sky is given in
+    const SUN_LUMINANCE: f32 = 2.0e6;
+
+    pub fn new(sky: &Sky) -> Self {
+        let elevation = sky.elevation.to_radians();
+        let azimuth = sky.azimuth.to_radians();
+        let sun = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            elevation.cos() * azimuth.cos(),
+        );
+
+        // The model only covers suns above the horizon, and turbidities it was fitted to
+        let t = sky.turbidity.clamp(1.7, 10.0);
+        let theta_s = (FRAC_PI_2 - elevation).clamp(0.0, FRAC_PI_2);
+
+        // Table 2 of the paper, in the order x, y, luminance
+        let perez = [
+            Vec4::new(
+                -0.0193 * t - 0.2592,
+                -0.0167 * t - 0.2608,
+                0.1787 * t - 1.4630,
+                0.0,
+            ),
+            Vec4::new(
+                -0.0665 * t + 0.0008,
+                -0.0950 * t + 0.0092,
+                -0.3554 * t + 0.4275,
+                0.0,
+            ),
+            Vec4::new(
+                -0.0004 * t + 0.2125,
+                -0.0079 * t + 0.2102,
+                -0.0227 * t + 5.3251,
+                0.0,
+            ),
+            Vec4::new(
+                -0.0641 * t - 0.8989,
+                -0.0441 * t - 1.6537,
+                0.1206 * t - 2.5771,
+                0.0,
+            ),
+            Vec4::new(
+                -0.0033 * t + 0.0452,
+                -0.0109 * t + 0.0529,
+                -0.0670 * t + 0.3703,
+                0.0,
+            ),
+        ];
+
+        // Zenith luminance and chromaticity, from appendix A.2
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let theta = Vec4::new(theta_s.powi(3), theta_s.powi(2), theta_s, 1.0);
+        let zenith_x = t * t * theta.dot(Vec4::new(0.00166, -0.00375, 0.00209, 0.0))
+            + t * theta.dot(Vec4::new(-0.02903, 0.06377, -0.03202, 0.00394))
+            + theta.dot(Vec4::new(0.11693, -0.21196, 0.06052, 0.25886));
+        let zenith_y = t * t * theta.dot(Vec4::new(0.00275, -0.00610, 0.00317, 0.0))
+            + t * theta.dot(Vec4::new(-0.04214, 0.08970, -0.04153, 0.00516))
+            + theta.dot(Vec4::new(0.15346, -0.26756, 0.06670, 0.26688));
+
+        // Dividing by the distribution at the zenith means it only needs evaluating once per ray
+        let zenith =
+            Vec4::new(zenith_x, zenith_y, zenith_luminance, 0.0) / perez_at_zenith(&perez, theta_s);
+
+        let sun_radiance = if sky.elevation > 0.0 {
+            Self::SUN_LUMINANCE * sun_transmittance(theta_s, t)
+        } else {
+            Vec3::ZERO
+        };
+
+        Self {
+            sun_direction: sun.extend(Self::SUN_RADIUS.cos()),
+            sun_radiance: sun_radiance.extend(0.0),
+            zenith,
+            perez,
+        }
+    }
+}
+
+// The Perez function for each channel, looking straight up with the sun at theta_s from there
+fn perez_at_zenith(perez: &[Vec4; 5], theta_s: f32) -> Vec4 {
+    let [a, b, c, d, e] = *perez;
+    let cos_gamma = theta_s.cos();
+    (1.0 + a * b.exp()) * (1.0 + c * (d * theta_s).exp() + e * cos_gamma * cos_gamma)
+}
+
+// Fraction of the sun's light reaching the ground in red, green and blue, after Rayleigh and
+// aerosol scattering, following appendix A.1 of the paper
+fn sun_transmittance(theta_s: f32, turbidity: f32) -> Vec3 {
+    // Relative optical mass of air along the path to the sun
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    // Wavelengths in micrometers
+    Vec3::from([0.680f32, 0.550, 0.440].map(|lambda| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+        rayleigh * aerosol
+    }))
+}
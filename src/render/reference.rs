@@ -0,0 +1,1034 @@
+use self::{
+    bsdf::{calc_onb, eval_bsdf, luminance, pdf_bsdf, sample_bsdf},
+    sky::{in_sun_disk, sky_radiance, sun_pdf},
+};
+use super::raytracer::shaders::ShaderUniforms;
+use crate::{
+    bvh::{Ray, SceneBvh},
+    loader::{
+        environment::Environment,
+        lights::{self, EmissiveTriangle, GpuLight, LightKind},
+        SceneData,
+    },
+    surfaces::Surfaces,
+    world::World,
+};
+use glam::{Mat3A, Vec2, Vec3, Vec3A, Vec4, Vec4Swizzles};
+use std::f32::consts::PI;
+
+mod bsdf;
+mod sky;
+
+// A pure CPU implementation of the path tracer in shaders/raytracer.comp.
+// It traces the same scene data with the same camera and integrator as the GPU, so it can be used
+// as a ground truth to compare against, or as a fallback on machines without ray query support.
+// Random numbers are seeded the same way, but the sequences drift apart wherever floating point
+// results differ slightly, so only converged images should be compared.
+//
+// Each function names the one it mirrors in the shader, and must be kept in step with it.
+// The tests at the bottom check that light sampling converges to the same result as BSDF sampling
+// alone, so the integrator can be verified without a GPU.
+
+// A material's factors multiplied by its textures at a hit
+struct Material {
+    base_color: Vec3A,
+    emissive: Vec3A,
+    roughness: f32,
+    metallic: f32,
+}
+
+// The vertex attributes needed to shade a primitive's triangles, beyond those in its surface
+struct Primitive {
+    vertices: Vec<Vec3A>,
+    normals: Vec<Vec3>,
+    tangents: Vec<Vec4>,
+}
+
+struct HitInfo {
+    pos: Vec3A,
+    normal: Vec3A,
+    shading_normal: Vec3A,
+    // The amount of indirect light reaching the surface, from the occlusion texture
+    occlusion: f32,
+    material: Material,
+    // Width of the ray cone at the hit
+    cone_width: f32,
+
+    // The mesh and geometry within it that was hit
+    mesh: usize,
+    geometry: u32,
+}
+
+pub struct ReferenceTracer {
+    bvh: SceneBvh,
+    surfaces: Surfaces,
+    // The primitives of each mesh, in the order of their geometries
+    meshes: Vec<Vec<Primitive>>,
+
+    // Transforms each instance's object space normals and tangents into world space
+    normal_transforms: Vec<Mat3A>,
+    tangent_transforms: Vec<Mat3A>,
+    lights: Vec<GpuLight>,
+
+    // Emissive triangles for next event estimation, and their summed power
+    emitters: Vec<EmissiveTriangle>,
+    emitter_power: f32,
+    environment: Environment,
+}
+
+impl ReferenceTracer {
+    // Matches the ray extents used by the rayQueryInitializeEXT call in the shader
+    const T_MIN: f32 = 0.0;
+    const T_MAX: f32 = 10000.0;
+
+    pub fn new(data: &SceneData) -> Self {
+        let bvh = SceneBvh::new(&data.meshes, &data.instances);
+
+        let meshes = data
+            .meshes
+            .iter()
+            .map(|mesh| {
+                mesh.primitives
+                    .iter()
+                    .map(|primitive| Primitive {
+                        vertices: primitive
+                            .vertices
+                            .chunks_exact(3)
+                            .map(Vec3A::from_slice)
+                            .collect(),
+                        normals: primitive.normals.clone(),
+                        tangents: primitive.tangents.clone(),
+                    })
+                    .collect::<Vec<Primitive>>()
+            })
+            .collect::<Vec<Vec<Primitive>>>();
+
+        let normal_transforms = data
+            .instances
+            .iter()
+            .map(|instance| Mat3A::from_mat4(instance.transform.inverse().transpose()))
+            .collect::<Vec<Mat3A>>();
+        let tangent_transforms = data
+            .instances
+            .iter()
+            .map(|instance| Mat3A::from_mat4(instance.transform))
+            .collect::<Vec<Mat3A>>();
+
+        let (emitters, emitter_power) =
+            lights::emissive_triangles(&data.meshes, &data.instances, &data.materials);
+
+        Self {
+            bvh,
+            surfaces: Surfaces::new(data),
+            meshes,
+            normal_transforms,
+            tangent_transforms,
+            lights: data.lights.clone(),
+            emitters,
+            emitter_power,
+            environment: Environment::black(),
+        }
+    }
+
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.environment = environment;
+    }
+
+    // Renders a single frame of `samples` paths per pixel, returning linear RGBA radiance
+    pub fn render(&self, world: &World, dims: glam::UVec2) -> Vec<f32> {
+        let uniforms = ShaderUniforms::new(world, dims);
+        let mut pixels = vec![0.0f32; (dims.x * dims.y * 4) as usize];
+
+        // Split the image into bands of rows, one for each thread
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let rows_per_thread = (dims.y as usize).div_ceil(threads);
+        let band_size = rows_per_thread * dims.x as usize * 4;
+
+        std::thread::scope(|scope| {
+            for (band, chunk) in pixels.chunks_mut(band_size).enumerate() {
+                let uniforms = &uniforms;
+                scope.spawn(move || {
+                    for (offset, pixel) in chunk.chunks_exact_mut(4).enumerate() {
+                        let index = band * rows_per_thread * dims.x as usize + offset;
+                        let id = glam::uvec2(index as u32 % dims.x, index as u32 / dims.x);
+
+                        let color = self.trace_pixel(uniforms, id, dims);
+                        pixel.copy_from_slice(&[color.x, color.y, color.z, 1.0]);
+                    }
+                });
+            }
+        });
+
+        pixels
+    }
+
+    // Mirrors main() in the shader, for a single invocation
+    fn trace_pixel(&self, uniforms: &ShaderUniforms, id: glam::UVec2, dims: glam::UVec2) -> Vec3A {
+        let seed = uniforms.seed;
+        let mut rng = Rng {
+            state: [
+                ((id.x ^ 1534) ^ seed) | 1,
+                ((id.y.wrapping_add(432812) ^ 941) ^ seed) | 1,
+                (329810u32.wrapping_mul(seed) ^ seed) | 1,
+            ],
+        };
+
+        // uv-space coordinates in the range [0, 1], flipped to match Vulkan
+        let mut uv = id.as_vec2() / dims.as_vec2();
+        uv.y = 1.0 - uv.y;
+        let coord = uv * 2.0 - 1.0;
+
+        let mut color = Vec3A::ZERO;
+        for _ in 0..uniforms.samples {
+            let target = uniforms.inv_proj * glam::vec4(coord.x, coord.y, 1.0, 1.0);
+            let phi = (target.xyz() / target.w).normalize().extend(0.0);
+            let mut dir = Vec3A::from((uniforms.inv_view * phi).xyz());
+            let mut origin = uniforms.pos;
+            let mut cone_width = 0.0;
+
+            // Orthographic rays all point forwards, from the pixel's point on the near plane
+            if uniforms.orthographic != 0 {
+                let near = uniforms.inv_proj * glam::vec4(coord.x, coord.y, 0.0, 1.0);
+                origin = Vec3A::from((uniforms.inv_view * (near / near.w)).xyz());
+                dir = Vec3A::from((uniforms.inv_view * glam::Vec4::Z).xyz());
+
+                // The cone doesn't widen, so it starts out as wide as a pixel
+                cone_width = 2.0 * uniforms.inv_proj.y_axis.y / dims.y as f32;
+            }
+
+            // Jitter the direction slightly, exactly as the shader does
+            dir += (rng.random() * 2.0 - 1.0) * 0.0001;
+
+            // Thin lens depth of field, focusing every ray through the same point
+            if uniforms.aperture > 0.0 {
+                let forward = Vec3A::from(uniforms.inv_view.z_axis.xyz());
+                let focus = origin + dir * (uniforms.focal_length / dir.dot(forward));
+
+                let lens = sample_aperture(rng.random(), uniforms.aperture_blades)
+                    * uniforms.aperture
+                    * 0.5;
+                origin += Vec3A::from(uniforms.inv_view.x_axis.xyz()) * lens.x
+                    + Vec3A::from(uniforms.inv_view.y_axis.xyz()) * lens.y;
+                dir = (focus - origin).normalize();
+            }
+
+            let ray = Ray { origin, dir };
+
+            color += self.pathtrace(ray, cone_width, uniforms, &mut rng);
+        }
+
+        color / uniforms.samples as f32
+    }
+
+    fn pathtrace(
+        &self,
+        mut ray: Ray,
+        mut cone_width: f32,
+        uniforms: &ShaderUniforms,
+        rng: &mut Rng,
+    ) -> Vec3A {
+        let mut result = Vec3A::ZERO;
+        let mut throughput = Vec3A::ONE;
+        let bounces = uniforms.bounces;
+
+        let sample_lights = !self.emitters.is_empty() && self.emitter_power > 0.0;
+        let sample_environment = uniforms.sky == 0 && self.environment.integral > 0.0;
+        let sample_sun =
+            uniforms.sky != 0 && uniforms.sky_model.sun_radiance.xyz().max_element() > 0.0;
+
+        // Pdf of the BSDF sample which generated the current ray, zero for camera rays
+        let mut bsdf_pdf = 0.0;
+
+        for i in 0..bounces {
+            let Some(hit) = self.intersect(&ray, cone_width, uniforms, rng) else {
+                result += self.miss_radiance(ray.dir, bsdf_pdf, uniforms) * throughput;
+                break;
+            };
+
+            let material = &hit.material;
+
+            // Emitters hit by BSDF sampling could also have been found by light sampling at the
+            // previous vertex, so weight them against each other
+            let mut emissive = material.emissive;
+            if sample_lights && bsdf_pdf > 0.0 && emissive.max_element() > 0.0 {
+                let dist = ray.origin.distance(hit.pos);
+                let cos_light = hit.normal.dot(ray.dir.normalize()).abs();
+                let factor = self.surfaces.material(hit.mesh, hit.geometry).emissive;
+                let light_pdf = self.light_pdf(factor, dist, cos_light);
+                emissive *= mis_weight(bsdf_pdf, light_pdf);
+            }
+
+            result += emissive * throughput;
+
+            // Shade the side of the surface the ray arrived from
+            let wo = -ray.dir.normalize();
+            let (geometric_normal, mut normal) = if hit.normal.dot(wo) < 0.0 {
+                (-hit.normal, -hit.shading_normal)
+            } else {
+                (hit.normal, hit.shading_normal)
+            };
+
+            // Interpolated normals can face away from the viewer near silhouettes
+            if normal.dot(wo) <= 0.0 {
+                normal = geometric_normal;
+            }
+
+            // Light sampling connects to one vertex further along the path, so skip it on the
+            // last bounce to keep the maximum path length the same as without it
+            if sample_lights && i + 1 < bounces {
+                result += throughput * self.sample_light(&hit, normal, geometric_normal, wo, rng);
+            }
+
+            if !self.lights.is_empty() && i + 1 < bounces {
+                result += throughput
+                    * self.sample_punctual_light(&hit, normal, geometric_normal, wo, rng);
+            }
+
+            if sample_environment && i + 1 < bounces {
+                result += throughput
+                    * self.sample_environment(&hit, normal, geometric_normal, wo, uniforms, rng);
+            }
+
+            if sample_sun && i + 1 < bounces {
+                result +=
+                    throughput * self.sample_sun(&hit, normal, geometric_normal, wo, uniforms, rng);
+            }
+
+            let Some(sample) = sample_bsdf(material, normal, wo, rng.random()) else {
+                break;
+            };
+
+            // Directions above the shading normal can still point into the surface itself
+            if sample.dir.dot(geometric_normal) <= 0.0 {
+                break;
+            }
+
+            ray.origin = hit.pos + sample.dir * 0.01;
+            ray.dir = sample.dir;
+            cone_width = hit.cone_width;
+            // glTF intends baked occlusion to only darken indirect light, so it's applied to the
+            // light gathered by continuing the path but not to light sampled directly
+            throughput *= sample.weight * hit.occlusion;
+            bsdf_pdf = sample.pdf;
+        }
+
+        result
+    }
+
+    // See missRadiance in the shader. bsdf_pdf is the pdf of the BSDF sample that generated the
+    // ray, or zero for camera rays, which can't have been found by light sampling
+    fn miss_radiance(&self, dir: Vec3A, bsdf_pdf: f32, uniforms: &ShaderUniforms) -> Vec3A {
+        let dir = dir.normalize();
+
+        if uniforms.sky != 0 {
+            let sky = &uniforms.sky_model;
+            let mut radiance = sky_radiance(sky, dir);
+            if in_sun_disk(sky, dir) {
+                let weight = if bsdf_pdf > 0.0 {
+                    mis_weight(bsdf_pdf, sun_pdf(sky))
+                } else {
+                    1.0
+                };
+                radiance += Vec3A::from(sky.sun_radiance.xyz()) * weight;
+            }
+
+            return radiance;
+        }
+
+        // Like emitters, the environment could also have been found by light sampling
+        let mut radiance = self.environment_radiance(dir, uniforms);
+        if self.environment.integral > 0.0 && bsdf_pdf > 0.0 {
+            radiance *= mis_weight(bsdf_pdf, self.environment_pdf(dir, uniforms));
+        }
+
+        radiance
+    }
+
+    // See environmentRadiance in the shader
+    fn environment_radiance(&self, dir: Vec3A, uniforms: &ShaderUniforms) -> Vec3A {
+        let uv = direction_to_equirect(dir.normalize(), uniforms.environment_rotation);
+        self.environment.sample(uv) * uniforms.environment_intensity
+    }
+
+    // See environmentPdf in the shader
+    fn environment_pdf(&self, dir: Vec3A, uniforms: &ShaderUniforms) -> f32 {
+        let sin_theta = (1.0 - dir.y * dir.y).max(0.0).sqrt();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let dims = self.environment.dims;
+        let uv = direction_to_equirect(dir, uniforms.environment_rotation);
+        let texel = (uv * dims.as_vec2()).as_uvec2().min(dims - 1);
+        let sin_row = (PI * (texel.y as f32 + 0.5) / dims.y as f32).sin();
+        let uv_pdf = luminance(self.environment.texel(texel.x, texel.y)) * sin_row
+            / self.environment.integral;
+
+        uv_pdf / (2.0 * PI * PI * sin_theta)
+    }
+
+    // See sampleEnvironment in the shader, which picks a row from the marginal distribution then
+    // a texel within it from that row's conditional distribution
+    fn sample_environment(
+        &self,
+        hit: &HitInfo,
+        normal: Vec3A,
+        geometric_normal: Vec3A,
+        wo: Vec3A,
+        uniforms: &ShaderUniforms,
+        rng: &mut Rng,
+    ) -> Vec3A {
+        let material = &hit.material;
+        let r = rng.random();
+        let [width, height] = self.environment.dims.to_array().map(|x| x as usize);
+
+        let marginal = &self.environment.cdf[..height];
+        let row = search_cdf(marginal, r.y);
+        let conditional = &self.environment.cdf[height + row * width..][..width];
+        let column = search_cdf(conditional, r.x);
+
+        let uv = glam::vec2(
+            cdf_offset(conditional, column, r.x),
+            cdf_offset(marginal, row, r.y),
+        ) / self.environment.dims.as_vec2();
+        let wi = equirect_to_direction(uv, uniforms.environment_rotation);
+
+        if normal.dot(wi) <= 0.0 || geometric_normal.dot(wi) <= 0.0 {
+            return Vec3A::ZERO;
+        }
+
+        let pdf = self.environment_pdf(wi, uniforms);
+        if pdf <= 0.0 {
+            return Vec3A::ZERO;
+        }
+
+        let contribution =
+            eval_bsdf(material, normal, wo, wi) * self.environment_radiance(wi, uniforms);
+        if contribution == Vec3A::ZERO {
+            return Vec3A::ZERO;
+        }
+
+        if !self.visible(hit.pos + wi * 0.01, wi, Self::T_MAX, rng) {
+            return Vec3A::ZERO;
+        }
+
+        let weight = mis_weight(pdf, pdf_bsdf(material, normal, wo, wi));
+        contribution * weight / pdf
+    }
+
+    // See sampleSun in the shader, which uniformly samples the cone the sun's disk subtends
+    fn sample_sun(
+        &self,
+        hit: &HitInfo,
+        normal: Vec3A,
+        geometric_normal: Vec3A,
+        wo: Vec3A,
+        uniforms: &ShaderUniforms,
+        rng: &mut Rng,
+    ) -> Vec3A {
+        let material = &hit.material;
+        let sky = &uniforms.sky_model;
+
+        let r = rng.random();
+        let cos_theta = 1.0 - r.x * (1.0 - sky.sun_direction.w);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * r.y;
+        let local = Vec3A::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+        let wi = (calc_onb(sky.sun_direction.xyz().into()) * local).normalize();
+
+        if normal.dot(wi) <= 0.0 || geometric_normal.dot(wi) <= 0.0 {
+            return Vec3A::ZERO;
+        }
+
+        let contribution =
+            eval_bsdf(material, normal, wo, wi) * Vec3A::from(sky.sun_radiance.xyz());
+        if contribution == Vec3A::ZERO {
+            return Vec3A::ZERO;
+        }
+
+        if !self.visible(hit.pos + wi * 0.01, wi, Self::T_MAX, rng) {
+            return Vec3A::ZERO;
+        }
+
+        let pdf = sun_pdf(sky);
+        let weight = mis_weight(pdf, pdf_bsdf(material, normal, wo, wi));
+        contribution * weight / pdf
+    }
+
+    // See lightPdf in the shader
+    fn light_pdf(&self, emissive: Vec3A, dist: f32, cos_light: f32) -> f32 {
+        let area_pdf = luminance(emissive) / self.emitter_power;
+        area_pdf * dist * dist / cos_light.max(1e-6)
+    }
+
+    // See sampleLight in the shader, which picks an emissive triangle by its power then a point
+    // uniformly on it
+    fn sample_light(
+        &self,
+        hit: &HitInfo,
+        normal: Vec3A,
+        geometric_normal: Vec3A,
+        wo: Vec3A,
+        rng: &mut Rng,
+    ) -> Vec3A {
+        let material = &hit.material;
+        let r = rng.random();
+        let index = self.emitters.partition_point(|light| light.cdf <= r.z);
+        let light = &self.emitters[index.min(self.emitters.len() - 1)];
+        let [v0, v1, v2] = light.vertices;
+
+        let su = r.x.sqrt();
+        let b = glam::vec2(1.0 - su, r.y * su);
+        let point = b.x * v0 + b.y * v1 + (1.0 - b.x - b.y) * v2;
+
+        let to_light = point - hit.pos;
+        let dist = to_light.length();
+        let wi = to_light / dist;
+
+        // Emitters are double sided, matching how they're treated when hit directly
+        let light_normal = (v1 - v0).cross(v2 - v0).normalize();
+        let cos_light = light_normal.dot(wi).abs();
+        if cos_light <= 0.0
+            || normal.dot(wi) <= 0.0
+            || geometric_normal.dot(wi) <= 0.0
+            || dist <= 0.02
+        {
+            return Vec3A::ZERO;
+        }
+
+        // Lights are picked by their emissive factor, but emit the factor times their texture
+        let weights = Vec3A::new(b.x, b.y, 1.0 - b.x - b.y);
+        let tex_coords =
+            self.surfaces
+                .tex_coords(light.mesh, light.geometry as u32, light.primitive, weights);
+        let texture = self
+            .surfaces
+            .material(light.mesh, light.geometry as u32)
+            .emissive_texture;
+        let emissive = light.emissive
+            * Vec3A::from(self.surfaces.sample(texture, tex_coords, Vec2::ZERO).xyz());
+
+        let contribution = eval_bsdf(material, normal, wo, wi) * emissive;
+        if contribution == Vec3A::ZERO {
+            return Vec3A::ZERO;
+        }
+
+        if !self.visible(hit.pos + wi * 0.01, wi, dist - 0.02, rng) {
+            return Vec3A::ZERO;
+        }
+
+        let pdf = self.light_pdf(light.emissive, dist, cos_light);
+        let weight = mis_weight(pdf, pdf_bsdf(material, normal, wo, wi));
+        contribution * weight / pdf
+    }
+
+    // See samplePunctualLight in the shader, which picks one light uniformly
+    fn sample_punctual_light(
+        &self,
+        hit: &HitInfo,
+        normal: Vec3A,
+        geometric_normal: Vec3A,
+        wo: Vec3A,
+        rng: &mut Rng,
+    ) -> Vec3A {
+        let material = &hit.material;
+        let count = self.lights.len();
+        let index = ((rng.random().x * count as f32) as usize).min(count - 1);
+        let light = &self.lights[index];
+
+        let mut radiance = light.color * light.intensity;
+
+        let (wi, dist) = if light.kind == LightKind::Directional {
+            (-light.direction, Self::T_MAX)
+        } else {
+            let to_light = light.position - hit.pos;
+            let dist = to_light.length();
+
+            let window = light
+                .range
+                .map_or(1.0, |range| (1.0 - (dist / range).powi(4)).clamp(0.0, 1.0));
+            radiance *= window / (dist * dist).max(1e-4);
+
+            if light.kind == LightKind::Spot {
+                let cos_inner = light.inner_cone_angle.cos();
+                let cos_outer = light.outer_cone_angle.cos();
+                let scale = 1.0 / (cos_inner - cos_outer).max(0.001);
+                let cd = light.direction.dot(-to_light / dist);
+                radiance *= ((cd - cos_outer) * scale).clamp(0.0, 1.0).powi(2);
+            }
+
+            (to_light / dist, dist)
+        };
+
+        if normal.dot(wi) <= 0.0 || geometric_normal.dot(wi) <= 0.0 || dist <= 0.02 {
+            return Vec3A::ZERO;
+        }
+
+        let contribution = eval_bsdf(material, normal, wo, wi) * radiance;
+        if contribution == Vec3A::ZERO {
+            return Vec3A::ZERO;
+        }
+
+        if !self.visible(hit.pos + wi * 0.01, wi, dist - 0.02, rng) {
+            return Vec3A::ZERO;
+        }
+
+        contribution * count as f32
+    }
+
+    // Returns true if nothing blocks the segment from origin along dir, up to dist
+    fn visible(&self, origin: Vec3A, dir: Vec3A, dist: f32, rng: &mut Rng) -> bool {
+        let ray = Ray { origin, dir };
+        let accept = |mesh, hit: &_| self.surfaces.alpha_test(mesh, hit, || rng.random().x);
+        self.bvh
+            .intersect(&ray, Self::T_MIN, dist, accept)
+            .is_none()
+    }
+
+    // See intersect and getHitInfo in the shader
+    fn intersect(
+        &self,
+        ray: &Ray,
+        cone_width: f32,
+        uniforms: &ShaderUniforms,
+        rng: &mut Rng,
+    ) -> Option<HitInfo> {
+        let hit = self
+            .bvh
+            .intersect(ray, Self::T_MIN, Self::T_MAX, |mesh, hit| {
+                self.surfaces.alpha_test(mesh, hit, || rng.random().x)
+            })?;
+
+        // Interpolate the vertex attributes with the hit's barycentrics, as the shader does
+        let mesh = hit.mesh as usize;
+        let primitive = &self.meshes[mesh][hit.geometry as usize];
+        let surface = self.surfaces.surface(mesh, hit.geometry);
+        let triangle = &surface.indices[hit.primitive as usize * 3..][..3];
+        let weights = Vec3A::new(
+            1.0 - hit.barycentrics.x - hit.barycentrics.y,
+            hit.barycentrics.x,
+            hit.barycentrics.y,
+        );
+
+        let (normal, tangent) = triangle
+            .iter()
+            .zip(weights.to_array())
+            .map(|(&index, weight)| {
+                let index = index as usize;
+                (
+                    primitive.normals[index] * weight,
+                    primitive.tangents[index] * weight,
+                )
+            })
+            .fold((Vec3::ZERO, Vec4::ZERO), |a, b| (a.0 + b.0, a.1 + b.1));
+
+        // Degenerate normals fall back to the geometric one
+        let instance = hit.instance as usize;
+        let shading_normal = (self.normal_transforms[instance] * Vec3A::from(normal))
+            .try_normalize()
+            .unwrap_or(hit.normal);
+
+        let tangent_sign = if tangent.w < 0.0 { -1.0 } else { 1.0 };
+        let tangent = (self.tangent_transforms[instance] * Vec3A::from(tangent.xyz()))
+            .try_normalize()
+            .unwrap_or(Vec3A::ZERO);
+
+        let tex_coords = self
+            .surfaces
+            .tex_coords(mesh, hit.geometry, hit.primitive, weights);
+
+        // Find how much of each texture the ray cone covers where it meets the surface, ignoring
+        // any widening or narrowing from curvature along the path
+        let cone_width = cone_width + uniforms.spread_angle * hit.t;
+        let [v0, v1, v2] = [0, 1, 2].map(|i| primitive.vertices[triangle[i] as usize]);
+        let object_to_world = self.tangent_transforms[instance];
+        let world_area = (object_to_world * (v1 - v0))
+            .cross(object_to_world * (v2 - v0))
+            .length();
+        let projected_width = cone_width / hit.normal.dot(ray.dir.normalize()).abs().max(1e-4);
+        let areas = self
+            .surfaces
+            .tex_coord_areas(mesh, hit.geometry, hit.primitive);
+        let footprint = projected_width * (areas / world_area.max(1e-20)).powf(0.5);
+
+        let mut hit = HitInfo {
+            pos: ray.origin + hit.t * ray.dir,
+            normal: hit.normal,
+            shading_normal,
+            occlusion: 1.0,
+            material: Material {
+                base_color: Vec3A::ZERO,
+                emissive: Vec3A::ZERO,
+                roughness: 0.0,
+                metallic: 0.0,
+            },
+            cone_width,
+            mesh,
+            geometry: hit.geometry,
+        };
+
+        self.apply_textures(
+            &mut hit,
+            tangent.extend(tangent_sign),
+            tex_coords,
+            footprint,
+        );
+        Some(hit)
+    }
+
+    // See applyTextures in the shader
+    fn apply_textures(&self, hit: &mut HitInfo, tangent: Vec4, tex_coords: Vec4, footprint: Vec2) {
+        let material = self.surfaces.material(hit.mesh, hit.geometry);
+        let sample = |texture| self.surfaces.sample(texture, tex_coords, footprint);
+
+        // Roughness is stored in the green channel, and metalness in the blue channel
+        let metallic_roughness = sample(material.metallic_roughness_texture);
+        hit.material = Material {
+            base_color: material.base_color
+                * Vec3A::from(sample(material.base_color_texture).xyz()),
+            emissive: material.emissive * Vec3A::from(sample(material.emissive_texture).xyz()),
+            roughness: material.roughness * metallic_roughness.y,
+            metallic: material.metallic * metallic_roughness.z,
+        };
+
+        let occlusion = sample(material.occlusion_texture).x;
+        hit.occlusion = 1.0 + material.occlusion_strength * (occlusion - 1.0);
+
+        let tangent_dir = Vec3A::from(tangent.xyz());
+        if material.normal_texture.is_some() && tangent_dir != Vec3A::ZERO {
+            let mut tangent_normal = Vec3A::from(sample(material.normal_texture).xyz()) * 2.0 - 1.0;
+            tangent_normal.x *= material.normal_scale;
+            tangent_normal.y *= material.normal_scale;
+
+            // Build the tangent frame, making the interpolated tangent perpendicular to the normal
+            let n = hit.shading_normal;
+            let t = (tangent_dir - n * n.dot(tangent_dir)).normalize();
+            let b = n.cross(t) * tangent.w;
+            hit.shading_normal = (Mat3A::from_cols(t, b, n) * tangent_normal).normalize();
+        }
+    }
+}
+
+// See directionToEquirect in the shader, with +y at the top of the image
+fn direction_to_equirect(dir: Vec3A, rotation: f32) -> Vec2 {
+    let phi = dir.z.atan2(dir.x) + rotation;
+    let theta = dir.y.clamp(-1.0, 1.0).acos();
+    glam::vec2((phi / (2.0 * PI) + 0.5).rem_euclid(1.0), theta / PI)
+}
+
+// See equirectToDirection in the shader
+fn equirect_to_direction(uv: Vec2, rotation: f32) -> Vec3A {
+    let phi = (uv.x - 0.5) * 2.0 * PI - rotation;
+    let theta = uv.y * PI;
+    Vec3A::new(
+        theta.sin() * phi.cos(),
+        theta.cos(),
+        theta.sin() * phi.sin(),
+    )
+}
+
+// See searchEnvironmentCdf in the shader, finding the first entry greater than r
+fn search_cdf(cdf: &[f32], r: f32) -> usize {
+    cdf.partition_point(|&entry| entry <= r).min(cdf.len() - 1)
+}
+
+// See cdfOffset in the shader, giving the continuous position of r within an entry of a CDF
+fn cdf_offset(cdf: &[f32], index: usize, r: f32) -> f32 {
+    let low = if index > 0 { cdf[index - 1] } else { 0.0 };
+    let high = cdf[index];
+    index as f32 + ((r - low) / (high - low).max(1e-20)).clamp(0.0, 1.0)
+}
+
+// Power heuristic with an exponent of 2, see misWeight in the shader
+fn mis_weight(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    a / (a + b).max(1e-20)
+}
+
+// See sampleAperture in random.glsl
+fn sample_aperture(r: Vec3A, blades: u32) -> glam::Vec2 {
+    if blades < 3 {
+        let s = r.x.sqrt();
+        let phi = 2.0 * PI * r.y;
+        return glam::vec2(s * phi.cos(), s * phi.sin());
+    }
+
+    let edge = 2.0 * PI / blades as f32;
+    let blade = (r.z * blades as f32).floor().min((blades - 1) as f32);
+    let a = glam::vec2((blade * edge).cos(), (blade * edge).sin());
+    let b = glam::vec2(((blade + 1.0) * edge).cos(), ((blade + 1.0) * edge).sin());
+
+    r.x.sqrt() * a.lerp(b, r.y)
+}
+
+// The same PCG based generator as random.glsl, using wrapping arithmetic to match GLSL's uints
+struct Rng {
+    state: [u32; 3],
+}
+
+impl Rng {
+    fn pcg3d(&mut self) -> [u32; 3] {
+        let mut v = self
+            .state
+            .map(|x| x.wrapping_mul(1664525).wrapping_add(1013904223));
+
+        v[0] = v[0].wrapping_add(v[1].wrapping_mul(v[2]));
+        v[1] = v[1].wrapping_add(v[2].wrapping_mul(v[0]));
+        v[2] = v[2].wrapping_add(v[0].wrapping_mul(v[1]));
+        v = v.map(|x| x ^ (x >> 16));
+        v[0] = v[0].wrapping_add(v[1].wrapping_mul(v[2]));
+        v[1] = v[1].wrapping_add(v[2].wrapping_mul(v[0]));
+        v[2] = v[2].wrapping_add(v[0].wrapping_mul(v[1]));
+
+        self.state = v;
+        v
+    }
+
+    // Returns a random vector in the range [0, 1)
+    fn random(&mut self) -> Vec3A {
+        let v = self.pcg3d();
+        (Vec3A::new(v[0] as f32, v[1] as f32, v[2] as f32) / u32::MAX as f32).fract()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::objects::{GpuInstance, GpuMaterial, GpuMesh, GpuPrimitive};
+    use gltf::material::AlphaMode;
+
+    const SAMPLES: usize = 20000;
+
+    fn material(base_color: Vec3A, emissive: Vec3A) -> GpuMaterial {
+        GpuMaterial {
+            base_color,
+            emissive,
+            roughness: 1.0,
+            metallic: 0.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+
+            alpha: 1.0,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            emissive_texture: None,
+            occlusion_texture: None,
+        }
+    }
+
+    // A flat shaded parallelogram spanning `u` and `v` either side of `center`, facing u x v
+    fn quad(center: Vec3A, u: Vec3A, v: Vec3A, material: usize) -> GpuPrimitive {
+        let corners = [
+            center - u - v,
+            center + u - v,
+            center - u + v,
+            center + u + v,
+        ];
+        let normal = Vec3::from(u.cross(v).normalize());
+
+        GpuPrimitive {
+            vertices: corners
+                .iter()
+                .flat_map(|corner| corner.to_array())
+                .collect(),
+            indices: vec![0, 1, 2, 2, 1, 3],
+            normals: vec![normal; 4],
+            tangents: vec![Vec4::ZERO; 4],
+            tex_coords: [vec![Vec2::ZERO; 4], vec![Vec2::ZERO; 4]],
+            material,
+        }
+    }
+
+    // A closed cube of the given half size around the origin, with its faces facing outwards
+    fn cube(size: f32, material: usize) -> Vec<GpuPrimitive> {
+        let axes = [Vec3A::X, Vec3A::Y, Vec3A::Z];
+        (0..3)
+            .flat_map(|axis| {
+                let u = axes[(axis + 1) % 3] * size;
+                let v = axes[(axis + 2) % 3] * size;
+                [
+                    quad(axes[axis] * size, u, v, material),
+                    quad(axes[axis] * -size, v, u, material),
+                ]
+            })
+            .collect()
+    }
+
+    fn scene(primitives: Vec<GpuPrimitive>, materials: Vec<GpuMaterial>) -> SceneData {
+        SceneData {
+            images: Vec::new(),
+            textures: Vec::new(),
+            meshes: vec![GpuMesh { primitives }],
+            instances: vec![GpuInstance {
+                mesh: 0,
+                transform: glam::Mat4::IDENTITY,
+            }],
+            materials,
+            lights: Vec::new(),
+            cameras: Vec::new(),
+        }
+    }
+
+    // An environment whose rows are filled with the radiance `row` gives for each of them
+    fn environment(dims: glam::UVec2, row: impl Fn(u32) -> f32) -> Environment {
+        let pixels = (0..dims.y)
+            .flat_map(|y| std::iter::repeat_n([row(y), row(y), row(y), 1.0], dims.x as usize))
+            .flatten()
+            .collect();
+
+        Environment::new(dims, pixels)
+    }
+
+    // Direct lighting only, a path bouncing off the first surface it hits and ending at the next
+    fn uniforms() -> ShaderUniforms {
+        let mut world = World::new();
+        world.settings.bounces = 2;
+        ShaderUniforms::new(&world, glam::uvec2(1, 1))
+    }
+
+    fn rng() -> Rng {
+        Rng {
+            state: [1534, 941, 329810],
+        }
+    }
+
+    // Averages the radiance the tracer finds along a ray
+    fn render(tracer: &ReferenceTracer, ray: &Ray, uniforms: &ShaderUniforms) -> Vec3A {
+        let mut rng = rng();
+        let sum = (0..SAMPLES)
+            .map(|_| {
+                let ray = Ray {
+                    origin: ray.origin,
+                    dir: ray.dir,
+                };
+                tracer.pathtrace(ray, 0.0, uniforms, &mut rng)
+            })
+            .sum::<Vec3A>();
+        sum / SAMPLES as f32
+    }
+
+    // Estimates the light reflected at a point lit by `incoming` with BSDF sampling alone, which
+    // doesn't depend on any of the light sampling or weighting the tracer does
+    fn bsdf_estimate(
+        material: &Material,
+        normal: Vec3A,
+        wo: Vec3A,
+        incoming: impl Fn(Vec3A) -> Vec3A,
+    ) -> Vec3A {
+        let mut rng = Rng { state: [7, 11, 13] };
+
+        let sum = (0..SAMPLES * 10)
+            .filter_map(|_| sample_bsdf(material, normal, wo, rng.random()))
+            .filter(|sample| sample.dir.dot(normal) > 0.0)
+            .map(|sample| sample.weight * incoming(sample.dir))
+            .sum::<Vec3A>();
+        sum / (SAMPLES * 10) as f32
+    }
+
+    fn assert_close(found: Vec3A, expected: Vec3A, tolerance: f32) {
+        let error = ((found - expected).abs() / expected.max(Vec3A::splat(1e-3))).max_element();
+        assert!(
+            error < tolerance,
+            "found {}, expected {} ({:.1}% off)",
+            found,
+            expected,
+            error * 100.0
+        );
+    }
+
+    fn white() -> Material {
+        Material {
+            base_color: Vec3A::ONE,
+            emissive: Vec3A::ZERO,
+            roughness: 1.0,
+            metallic: 0.0,
+        }
+    }
+
+    // Looks straight at the front of a cube of half size one at the origin
+    fn front_ray() -> Ray {
+        Ray {
+            origin: Vec3A::new(0.0, 0.0, -4.0),
+            dir: Vec3A::Z,
+        }
+    }
+
+    #[test]
+    fn white_furnace() {
+        // Inside a constant environment a white surface reflects the environment's radiance,
+        // scaled by how much of the light the BSDF keeps
+        let data = scene(cube(1.0, 0), vec![material(Vec3A::ONE, Vec3A::ZERO)]);
+        let mut tracer = ReferenceTracer::new(&data);
+        tracer.set_environment(environment(glam::uvec2(16, 8), |_| 1.0));
+
+        let uniforms = uniforms();
+        let found = render(&tracer, &front_ray(), &uniforms);
+        let expected = bsdf_estimate(&white(), -Vec3A::Z, -Vec3A::Z, |_| Vec3A::ONE);
+
+        assert_close(found, expected, 0.01);
+        assert!(found.max_element() <= 1.0 && found.min_element() > 0.9);
+    }
+
+    #[test]
+    fn environment_sampling_is_unbiased() {
+        // A bright band around the top of the sky, which importance sampling concentrates on
+        let data = scene(cube(1.0, 0), vec![material(Vec3A::ONE, Vec3A::ZERO)]);
+        let mut tracer = ReferenceTracer::new(&data);
+        tracer.set_environment(environment(glam::uvec2(64, 32), |row| {
+            if (4..8).contains(&row) {
+                20.0
+            } else {
+                0.5
+            }
+        }));
+
+        let uniforms = uniforms();
+        let found = render(&tracer, &front_ray(), &uniforms);
+        let expected = bsdf_estimate(&white(), -Vec3A::Z, -Vec3A::Z, |dir| {
+            tracer.environment_radiance(dir, &uniforms)
+        });
+
+        assert_close(found, expected, 0.03);
+    }
+
+    #[test]
+    fn emitter_sampling_is_unbiased() {
+        // A small emissive square hanging over a large diffuse plane, seen from the side
+        let data = scene(
+            vec![
+                quad(Vec3A::ZERO, Vec3A::Z * 10.0, Vec3A::X * 10.0, 0),
+                quad(Vec3A::Y, Vec3A::X * 0.5, Vec3A::Z * 0.5, 1),
+            ],
+            vec![
+                material(Vec3A::ONE, Vec3A::ZERO),
+                material(Vec3A::ZERO, Vec3A::splat(4.0)),
+            ],
+        );
+        let tracer = ReferenceTracer::new(&data);
+
+        let origin = Vec3A::new(0.0, 0.5, -3.0);
+        let ray = Ray {
+            origin,
+            dir: -origin.normalize(),
+        };
+
+        let uniforms = uniforms();
+        let found = render(&tracer, &ray, &uniforms);
+        let expected = bsdf_estimate(&white(), Vec3A::Y, origin.normalize(), |dir| {
+            // Where the direction from the origin crosses the plane of the square
+            let point = dir / dir.y;
+            if point.x.abs() <= 0.5 && point.z.abs() <= 0.5 {
+                Vec3A::splat(4.0)
+            } else {
+                Vec3A::ZERO
+            }
+        });
+
+        assert_close(found, expected, 0.03);
+    }
+}
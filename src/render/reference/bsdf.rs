@@ -0,0 +1,182 @@
+use super::Material;
+use glam::{Vec2, Vec3A};
+use std::f32::consts::PI;
+
+// A CPU implementation of shaders/bsdf.glsl, working in the same y-up local shading frame
+
+pub struct BsdfSample {
+    pub dir: Vec3A,
+    pub weight: Vec3A,
+    pub pdf: f32,
+}
+
+struct BsdfParams {
+    diffuse: Vec3A,
+    f0: Vec3A,
+    alpha: f32,
+}
+
+impl BsdfParams {
+    fn new(material: &Material) -> Self {
+        let roughness = material.roughness.clamp(0.03, 1.0);
+
+        Self {
+            diffuse: material.base_color * (1.0 - material.metallic),
+            f0: Vec3A::splat(0.04).lerp(material.base_color, material.metallic),
+            alpha: roughness * roughness,
+        }
+    }
+
+    fn specular_probability(&self, n_o_v: f32) -> f32 {
+        let fresnel = fresnel_schlick(self.f0, n_o_v);
+        let specular = luminance(fresnel);
+        let diffuse = luminance(self.diffuse * (1.0 - fresnel));
+        (specular / (specular + diffuse).max(0.0001)).clamp(0.1, 0.9)
+    }
+
+    fn eval(&self, v: Vec3A, l: Vec3A) -> Vec3A {
+        if v.y <= 0.0 || l.y <= 0.0 {
+            return Vec3A::ZERO;
+        }
+
+        let h = (v + l).normalize();
+        let n_o_h = h.y.max(0.0);
+        let v_o_h = v.dot(h).max(0.0);
+
+        let fresnel = fresnel_schlick(self.f0, v_o_h);
+        let d = ggx_distribution(n_o_h, self.alpha);
+        let g = smith_g2(v.y, l.y, self.alpha);
+
+        let specular = fresnel * d * g / (4.0 * v.y * l.y);
+        let diffuse = (1.0 - fresnel) * self.diffuse / PI;
+        (diffuse + specular) * l.y
+    }
+
+    fn pdf(&self, v: Vec3A, l: Vec3A) -> f32 {
+        if v.y <= 0.0 || l.y <= 0.0 {
+            return 0.0;
+        }
+
+        let h = (v + l).normalize();
+        let specular_pdf =
+            smith_g1(v.y, self.alpha) * ggx_distribution(h.y.max(0.0), self.alpha) / (4.0 * v.y);
+        let diffuse_pdf = l.y / PI;
+
+        let p = self.specular_probability(v.y);
+        p * specular_pdf + (1.0 - p) * diffuse_pdf
+    }
+}
+
+// See evalBsdf in the shader
+pub fn eval_bsdf(material: &Material, n: Vec3A, wo: Vec3A, wi: Vec3A) -> Vec3A {
+    let to_local = calc_onb(n).transpose();
+    BsdfParams::new(material).eval(to_local * wo, to_local * wi)
+}
+
+// See pdfBsdf in the shader
+pub fn pdf_bsdf(material: &Material, n: Vec3A, wo: Vec3A, wi: Vec3A) -> f32 {
+    let to_local = calc_onb(n).transpose();
+    BsdfParams::new(material).pdf(to_local * wo, to_local * wi)
+}
+
+// See sampleBsdf in the shader
+pub fn sample_bsdf(material: &Material, n: Vec3A, wo: Vec3A, r: Vec3A) -> Option<BsdfSample> {
+    let onb = calc_onb(n);
+    let v = onb.transpose() * wo;
+    if v.y <= 0.0 {
+        return None;
+    }
+
+    let params = BsdfParams::new(material);
+
+    let l = if r.z < params.specular_probability(v.y) {
+        let h = sample_ggx_vndf(v, params.alpha, r.truncate());
+        reflect(-v, h)
+    } else {
+        cosine_sample_hemisphere(r.truncate())
+    };
+
+    let pdf = params.pdf(v, l);
+    if pdf <= 0.0 {
+        return None;
+    }
+
+    Some(BsdfSample {
+        dir: (onb * l).normalize(),
+        weight: params.eval(v, l) / pdf,
+        pdf,
+    })
+}
+
+// See calcONB in bsdf.glsl
+pub fn calc_onb(n: Vec3A) -> glam::Mat3A {
+    let s = if n.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (s + n.z);
+    let b = n.x * n.y * a;
+
+    glam::Mat3A::from_cols(
+        Vec3A::new(1.0 + s * n.x * n.x * a, s * b, -s * n.x),
+        n,
+        Vec3A::new(b, s + n.y * n.y * a, -n.y),
+    )
+}
+
+pub fn luminance(color: Vec3A) -> f32 {
+    color.dot(Vec3A::new(0.2126, 0.7152, 0.0722))
+}
+
+fn fresnel_schlick(f0: Vec3A, cos_theta: f32) -> Vec3A {
+    f0 + (1.0 - f0) * (1.0 - cos_theta).powi(5)
+}
+
+fn ggx_distribution(n_o_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_o_h * n_o_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+fn smith_g1(n_o_x: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    2.0 * n_o_x / (n_o_x + (a2 + (1.0 - a2) * n_o_x * n_o_x).sqrt())
+}
+
+fn smith_g2(n_o_v: f32, n_o_l: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let v = n_o_l * (a2 + (1.0 - a2) * n_o_v * n_o_v).sqrt();
+    let l = n_o_v * (a2 + (1.0 - a2) * n_o_l * n_o_l).sqrt();
+    2.0 * n_o_v * n_o_l / (v + l)
+}
+
+// See sampleGgxVndf in the shader
+fn sample_ggx_vndf(v: Vec3A, alpha: f32, r: Vec2) -> Vec3A {
+    let vh = Vec3A::new(alpha * v.x, v.y, alpha * v.z).normalize();
+
+    let len_sq = vh.x * vh.x + vh.z * vh.z;
+    let t1 = if len_sq > 0.0 {
+        Vec3A::new(-vh.z, 0.0, vh.x) / len_sq.sqrt()
+    } else {
+        Vec3A::X
+    };
+    let t2 = t1.cross(vh);
+
+    let radius = r.x.sqrt();
+    let phi = 2.0 * PI * r.y;
+    let p1 = radius * phi.cos();
+    let p2 = radius * phi.sin();
+    let s = 0.5 * (1.0 + vh.y);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * p2;
+
+    let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+    Vec3A::new(alpha * nh.x, nh.y.max(0.0), alpha * nh.z).normalize()
+}
+
+// See cosineSampleHemisphere in random.glsl
+fn cosine_sample_hemisphere(r: Vec2) -> Vec3A {
+    let s = r.x.sqrt();
+    let phi = 2.0 * PI * r.y;
+    Vec3A::new(s * phi.cos(), (1.0 - r.x).max(0.0).sqrt(), s * phi.sin())
+}
+
+fn reflect(i: Vec3A, n: Vec3A) -> Vec3A {
+    i - 2.0 * n.dot(i) * n
+}
@@ -0,0 +1,40 @@
+use crate::render::raytracer::sky::SkyModel;
+use glam::{Mat3A, Vec3A, Vec4Swizzles};
+use std::f32::consts::PI;
+
+// A CPU implementation of shaders/sky.glsl
+
+// See skyRadiance in the shader
+pub fn sky_radiance(sky: &SkyModel, dir: Vec3A) -> Vec3A {
+    let cos_theta = dir.y.max(0.001);
+    let cos_gamma = dir.dot(sky.sun_direction.xyz().into()).clamp(-1.0, 1.0);
+    let gamma = cos_gamma.acos();
+
+    let [a, b, c, d, e] = sky
+        .perez
+        .map(|coefficients| Vec3A::from(coefficients.xyz()));
+    let perez =
+        (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+
+    let xy_y = Vec3A::from(sky.zenith.xyz()) * perez;
+    let xyz = Vec3A::new(xy_y.x, xy_y.y, 1.0 - xy_y.x - xy_y.y) * (xy_y.z / xy_y.y);
+
+    let xyz_to_srgb = Mat3A::from_cols_array(&[
+        3.2406, -0.9689, 0.0557, -1.5372, 1.8758, -0.2040, -0.4986, 0.0415, 1.0570,
+    ]);
+    (xyz_to_srgb * xyz).max(Vec3A::ZERO)
+}
+
+// See inSunDisk in the shader
+pub fn in_sun_disk(sky: &SkyModel, dir: Vec3A) -> bool {
+    dir.dot(sky.sun_direction.xyz().into()) >= sky.sun_direction.w
+}
+
+// See sunPdf in the shader
+pub fn sun_pdf(sky: &SkyModel) -> f32 {
+    1.0 / (2.0 * PI * (1.0 - sky.sun_direction.w))
+}
+
+fn exp(v: Vec3A) -> Vec3A {
+    Vec3A::from(v.to_array().map(f32::exp))
+}
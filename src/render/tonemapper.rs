@@ -0,0 +1,253 @@
+use super::frame::FrameRef;
+use crate::{
+    vulkan::{
+        command::CommandList,
+        context::Context,
+        descriptor::{
+            DescriptorBinding, DescriptorImageWrite, DescriptorPool, DescriptorSet,
+            DescriptorSetLayout,
+        },
+        image::{Image, ImageView},
+        pipeline::{ComputePipeline, PipelineLayout},
+        shader::Shader,
+    },
+    world::{Tonemap, World},
+};
+use ash::vk;
+use glam::{Mat3, Vec3};
+use std::sync::Arc;
+
+mod shader {
+    include!(concat!(env!("OUT_DIR"), "/tonemap.comp.rs"));
+}
+
+#[derive(bytemuck::Zeroable, bytemuck::Pod, Clone, Copy)]
+#[repr(C)]
+struct PushConstants {
+    exposure: f32,
+    tonemap: u32,
+}
+
+// Display pass which maps the raytracer's HDR output into the frame's image, ready for the
+// interface to be painted on top
+pub struct Tonemapper {
+    descriptor_pool: DescriptorPool,
+    descriptor_layout: DescriptorSetLayout,
+    pipeline_layout: PipelineLayout,
+    shader: Shader,
+    pipeline: ComputePipeline,
+    descriptor_sets: Vec<DescriptorSet>,
+}
+
+impl Tonemapper {
+    const WORKGROUP_SIZE: u32 = 16;
+
+    pub fn new(context: Arc<Context>) -> Self {
+        let descriptor_pool = DescriptorPool::new(context.clone());
+
+        let bindings = vec![
+            DescriptorBinding {
+                binding: 0,
+                count: 1,
+                kind: vk::DescriptorType::STORAGE_IMAGE,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
+            DescriptorBinding {
+                binding: 1,
+                count: 1,
+                kind: vk::DescriptorType::STORAGE_IMAGE,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
+        ];
+
+        let descriptor_layout = DescriptorSetLayout::new(context.clone(), bindings);
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<PushConstants>() as u32,
+        };
+
+        let pipeline_layout = PipelineLayout::new(
+            context.clone(),
+            push_constants,
+            std::slice::from_ref(&descriptor_layout),
+        );
+
+        let shader = Shader::new(
+            context.clone(),
+            &shader::CODE,
+            vk::ShaderStageFlags::COMPUTE,
+            "main",
+        );
+
+        let pipeline = ComputePipeline::new(context.clone(), &shader, &pipeline_layout);
+        let descriptor_sets = descriptor_pool.allocate(&context, &descriptor_layout, 3);
+
+        Self {
+            descriptor_pool,
+            descriptor_layout,
+            pipeline_layout,
+            shader,
+            pipeline,
+            descriptor_sets,
+        }
+    }
+
+    // Tonemaps `hdr` into the frame's image, which is left ready to be drawn over
+    pub fn run(&self, cmds: &CommandList, frame: &FrameRef, world: &World, hdr: &ImageView) {
+        let descriptor_set = self.descriptor_sets.get(frame.index()).unwrap();
+        descriptor_set.write(
+            &[
+                DescriptorImageWrite {
+                    image_view: hdr,
+                    layout: vk::ImageLayout::GENERAL,
+                    binding: 0,
+                    sampler: None,
+                    image_kind: vk::DescriptorType::STORAGE_IMAGE,
+                },
+                DescriptorImageWrite {
+                    image_view: frame.image_view(),
+                    layout: vk::ImageLayout::GENERAL,
+                    binding: 1,
+                    sampler: None,
+                    image_kind: vk::DescriptorType::STORAGE_IMAGE,
+                },
+            ],
+            &[],
+        );
+
+        let image_memory_barriers = [vk::ImageMemoryBarrier2 {
+            src_stage_mask: vk::PipelineStageFlags2::NONE,
+            src_access_mask: vk::AccessFlags2::NONE,
+            dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+            dst_access_mask: vk::AccessFlags2::SHADER_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::GENERAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: frame.image().handle,
+            subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
+            ..Default::default()
+        }];
+
+        cmds.pipeline_barrier(&image_memory_barriers, &[]);
+
+        cmds.bind_compute_pipeline(&self.pipeline);
+        cmds.bind_descriptor_sets(
+            vk::PipelineBindPoint::COMPUTE,
+            &self.pipeline_layout,
+            &[descriptor_set.handle],
+        );
+
+        cmds.push_constants(
+            &self.pipeline_layout,
+            vk::ShaderStageFlags::COMPUTE,
+            PushConstants {
+                exposure: world.settings.exposure,
+                tonemap: world.settings.tonemap as u32,
+            },
+        );
+
+        cmds.dispatch(
+            frame.dims().x.div_ceil(Self::WORKGROUP_SIZE),
+            frame.dims().y.div_ceil(Self::WORKGROUP_SIZE),
+            1,
+        );
+
+        let image_memory_barriers = [vk::ImageMemoryBarrier2 {
+            src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+            src_access_mask: vk::AccessFlags2::SHADER_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags2::NONE,
+            dst_access_mask: vk::AccessFlags2::NONE,
+            old_layout: vk::ImageLayout::GENERAL,
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: frame.image().handle,
+            subresource_range: Image::default_subresource(vk::ImageAspectFlags::COLOR),
+            ..Default::default()
+        }];
+        cmds.pipeline_barrier(&image_memory_barriers, &[]);
+    }
+}
+
+// A CPU implementation of shaders/tonemap.comp, returning sRGB encoded values in [0, 1]
+pub fn tonemap(color: Vec3, exposure: f32, tonemap: Tonemap) -> Vec3 {
+    let color = color * exposure;
+
+    let mapped = match tonemap {
+        Tonemap::Reinhard => color / (color + 1.0),
+        Tonemap::Aces => aces(color),
+        Tonemap::AgX => agx(color),
+    };
+
+    mapped
+        .clamp(Vec3::ZERO, Vec3::ONE)
+        .to_array()
+        .map(linear_to_srgb)
+        .into()
+}
+
+fn aces(color: Vec3) -> Vec3 {
+    let input = Mat3::from_cols_array(&[
+        0.59719, 0.07600, 0.02840, 0.35458, 0.90834, 0.13383, 0.04823, 0.01566, 0.83777,
+    ]);
+
+    let output = Mat3::from_cols_array(&[
+        1.60475, -0.10208, -0.00327, -0.53108, 1.10813, -0.07276, -0.07367, -0.00605, 1.07602,
+    ]);
+
+    let v = input * color;
+    let v = (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081);
+    output * v
+}
+
+fn agx(color: Vec3) -> Vec3 {
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let inset = Mat3::from_cols_array(&[
+        0.84247905,
+        0.042328242,
+        0.042375654,
+        0.0784336,
+        0.87846863,
+        0.0784336,
+        0.079223745,
+        0.07916613,
+        0.879143,
+    ]);
+
+    let outset = Mat3::from_cols_array(&[
+        1.196879,
+        -0.052896854,
+        -0.052971635,
+        -0.09802088,
+        1.1519032,
+        -0.09804345,
+        -0.09902974,
+        -0.098961174,
+        1.1510737,
+    ]);
+
+    let v = (inset * color).max(Vec3::splat(1e-10));
+    let v = Vec3::from(v.to_array().map(f32::log2)).clamp(Vec3::splat(MIN_EV), Vec3::splat(MAX_EV));
+    let x = (v - MIN_EV) / (MAX_EV - MIN_EV);
+
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    let v =
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232;
+
+    let v = (outset * v).clamp(Vec3::ZERO, Vec3::ONE);
+    v.powf(2.2)
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c < 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
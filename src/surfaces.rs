@@ -0,0 +1,268 @@
+use crate::{
+    bvh::MeshHit,
+    loader::{
+        images::GpuTexture,
+        objects::{GpuMaterial, TextureSlot},
+        SceneData,
+    },
+};
+use ash::vk;
+use glam::{IVec2, UVec2, Vec2, Vec3A, Vec4, Vec4Swizzles};
+use gltf::material::AlphaMode;
+
+// The parts of a scene's surfaces that the shader reads through the geometry and material buffers,
+// for shading the hits a SceneBvh finds on the CPU
+
+// The indices and texture coordinates of one of a mesh's geometries
+pub struct Surface {
+    pub indices: Vec<u32>,
+    pub tex_coords: [Vec<Vec2>; 2],
+    pub material: usize,
+}
+
+// One level of a texture's mip chain, decoded to linear values
+struct Level {
+    dims: UVec2,
+    texels: Vec<Vec4>,
+}
+
+// A texture's image along with its sampler state, filtered the way Vulkan filters it
+struct Texture {
+    levels: Vec<Level>,
+    address_modes: [vk::SamplerAddressMode; 2],
+    mag_filter: vk::Filter,
+    min_filter: vk::Filter,
+    mipmap_mode: vk::SamplerMipmapMode,
+}
+
+impl Texture {
+    fn new(texture: &GpuTexture, data: &SceneData) -> Self {
+        let image = &data.images[texture.image];
+        let levels = (0..image.mip_offsets.len())
+            .map(|level| Level {
+                dims: image.level_dims(level),
+                texels: image.texels(level),
+            })
+            .collect::<Vec<Level>>();
+
+        Self {
+            levels,
+            address_modes: texture.address_modes,
+            mag_filter: texture.mag_filter,
+            min_filter: texture.min_filter,
+            mipmap_mode: texture.mipmap_mode,
+        }
+    }
+
+    // Looks up a texel, applying the sampler's address mode to coordinates outside the level
+    fn texel(&self, level: &Level, texel: IVec2) -> Vec4 {
+        let wrap = |coord: i32, size: u32, mode: vk::SamplerAddressMode| {
+            let size = size as i32;
+            match mode {
+                vk::SamplerAddressMode::REPEAT => coord.rem_euclid(size),
+                vk::SamplerAddressMode::MIRRORED_REPEAT => {
+                    let coord = coord.rem_euclid(size * 2);
+                    if coord < size {
+                        coord
+                    } else {
+                        size * 2 - 1 - coord
+                    }
+                }
+                _ => coord.clamp(0, size - 1),
+            }
+        };
+
+        let x = wrap(texel.x, level.dims.x, self.address_modes[0]);
+        let y = wrap(texel.y, level.dims.y, self.address_modes[1]);
+        level.texels[(y as u32 * level.dims.x + x as u32) as usize]
+    }
+
+    fn filter(&self, level: usize, uv: Vec2, filter: vk::Filter) -> Vec4 {
+        let level = &self.levels[level];
+        let position = uv * level.dims.as_vec2();
+
+        if filter == vk::Filter::NEAREST {
+            return self.texel(level, position.floor().as_ivec2());
+        }
+
+        // Bilinear filtering between the four texels around the sample, whose centres are offset
+        // by half a texel
+        let position = position - 0.5;
+        let base = position.floor();
+        let t = position - base;
+        let base = base.as_ivec2();
+
+        let top = self
+            .texel(level, base)
+            .lerp(self.texel(level, base + IVec2::X), t.x);
+        let bottom = self
+            .texel(level, base + IVec2::Y)
+            .lerp(self.texel(level, base + IVec2::ONE), t.x);
+        top.lerp(bottom, t.y)
+    }
+
+    // Mirrors textureLod with the texture's sampler, which magnifies at a level of detail of zero
+    // and below, and otherwise picks or blends levels of the mip chain
+    fn sample(&self, uv: Vec2, lod: f32) -> Vec4 {
+        if lod <= 0.0 {
+            return self.filter(0, uv, self.mag_filter);
+        }
+
+        let last = (self.levels.len() - 1) as f32;
+        let lod = lod.min(last);
+
+        if self.mipmap_mode == vk::SamplerMipmapMode::NEAREST {
+            // Vulkan rounds halfway levels down
+            let level = (lod + 0.5).ceil() - 1.0;
+            return self.filter(level as usize, uv, self.min_filter);
+        }
+
+        let level = lod.floor();
+        let upper = (level + 1.0).min(last);
+        let lower = self.filter(level as usize, uv, self.min_filter);
+        let upper = self.filter(upper as usize, uv, self.min_filter);
+        lower.lerp(upper, lod - level)
+    }
+}
+
+pub struct Surfaces {
+    // The surfaces of each mesh, in the order of their geometries
+    meshes: Vec<Vec<Surface>>,
+    pub materials: Vec<GpuMaterial>,
+    // Textures that weren't kept are sampled as one
+    textures: Vec<Option<Texture>>,
+}
+
+impl Surfaces {
+    pub fn new(data: &SceneData) -> Self {
+        Self::build(data, |_| true)
+    }
+
+    // Only keeps the textures that alpha tests read, as decoding every texture would use a lot of
+    // memory for something that's just used to pick what's under the cursor
+    pub fn for_alpha_testing(data: &SceneData) -> Self {
+        Self::build(data, |texture| {
+            data.materials.iter().any(|material| {
+                material.alpha_mode != AlphaMode::Opaque
+                    && material
+                        .base_color_texture
+                        .is_some_and(|slot| slot.texture == texture)
+            })
+        })
+    }
+
+    fn build(data: &SceneData, keep: impl Fn(usize) -> bool) -> Self {
+        let meshes = data
+            .meshes
+            .iter()
+            .map(|mesh| {
+                mesh.primitives
+                    .iter()
+                    .map(|primitive| Surface {
+                        indices: primitive.indices.clone(),
+                        tex_coords: primitive.tex_coords.clone(),
+                        material: primitive.material,
+                    })
+                    .collect::<Vec<Surface>>()
+            })
+            .collect::<Vec<Vec<Surface>>>();
+
+        let textures = data
+            .textures
+            .iter()
+            .enumerate()
+            .map(|(index, texture)| keep(index).then(|| Texture::new(texture, data)))
+            .collect::<Vec<Option<Texture>>>();
+
+        Self {
+            meshes,
+            materials: data.materials.clone(),
+            textures,
+        }
+    }
+
+    pub fn surface(&self, mesh: usize, geometry: u32) -> &Surface {
+        &self.meshes[mesh][geometry as usize]
+    }
+
+    pub fn material(&self, mesh: usize, geometry: u32) -> &GpuMaterial {
+        &self.materials[self.surface(mesh, geometry).material]
+    }
+
+    // Interpolates both sets of texture coordinates over a triangle, given a weight for each of its
+    // corners. The first set is returned in xy, and the second in zw
+    pub fn tex_coords(&self, mesh: usize, geometry: u32, primitive: u32, weights: Vec3A) -> Vec4 {
+        let surface = self.surface(mesh, geometry);
+        let triangle = &surface.indices[primitive as usize * 3..][..3];
+
+        let [uv0, uv1] = [0, 1].map(|set| {
+            triangle
+                .iter()
+                .zip(weights.to_array())
+                .map(|(&index, weight)| surface.tex_coords[set][index as usize] * weight)
+                .sum::<Vec2>()
+        });
+
+        glam::vec4(uv0.x, uv0.y, uv1.x, uv1.y)
+    }
+
+    // See texCoordAreas in the shader, returning twice the area each set of texture coordinates
+    // covers on a triangle
+    pub fn tex_coord_areas(&self, mesh: usize, geometry: u32, primitive: u32) -> Vec2 {
+        let surface = self.surface(mesh, geometry);
+        let triangle = &surface.indices[primitive as usize * 3..][..3];
+
+        let [area0, area1] = [0, 1].map(|set| {
+            let [c0, c1, c2] = [0, 1, 2].map(|i| surface.tex_coords[set][triangle[i] as usize]);
+            (c1 - c0).perp_dot(c2 - c0).abs()
+        });
+
+        glam::vec2(area0, area1)
+    }
+
+    // See sampleTexture in the shader, returning one if the material doesn't use the texture.
+    // The mip level is picked from the footprint of the ray cone, pass zero for the full resolution
+    pub fn sample(&self, slot: Option<TextureSlot>, tex_coords: Vec4, footprint: Vec2) -> Vec4 {
+        let Some(slot) = slot else {
+            return Vec4::ONE;
+        };
+
+        let (uv, width) = if slot.tex_coord == 0 {
+            (tex_coords.xy(), footprint.x)
+        } else {
+            (tex_coords.zw(), footprint.y)
+        };
+
+        self.textures[slot.texture]
+            .as_ref()
+            .map_or(Vec4::ONE, |texture| {
+                let size = texture.levels[0].dims.as_vec2();
+                let lod = (width * (size.x * size.y).sqrt()).log2().max(0.0);
+                texture.sample(uv, lod)
+            })
+    }
+
+    // See alphaTest in the shader, where `random` is only called for blended materials
+    pub fn alpha_test(&self, mesh: usize, hit: &MeshHit, random: impl FnOnce() -> f32) -> bool {
+        let material = self.material(mesh, hit.geometry);
+        if material.alpha_mode == AlphaMode::Opaque {
+            return true;
+        }
+
+        let weights = Vec3A::new(
+            1.0 - hit.barycentrics.x - hit.barycentrics.y,
+            hit.barycentrics.x,
+            hit.barycentrics.y,
+        );
+        let tex_coords = self.tex_coords(mesh, hit.geometry, hit.primitive, weights);
+        // Candidates have no ray cone yet, so the texture is sampled at its full resolution
+        let texture = self.sample(material.base_color_texture, tex_coords, Vec2::ZERO);
+        let alpha = material.alpha * texture.w;
+
+        match material.alpha_mode {
+            AlphaMode::Mask => alpha >= material.alpha_cutoff,
+            AlphaMode::Blend => random() < alpha,
+            AlphaMode::Opaque => true,
+        }
+    }
+}
@@ -152,6 +152,18 @@ impl CommandList {
         }
     }
 
+    pub fn copy_to_buffer(&self, image: &Image, buffer: &Buffer, region: &[vk::BufferImageCopy]) {
+        unsafe {
+            self.context.device.cmd_copy_image_to_buffer(
+                self.handle,
+                image.handle,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer.handle,
+                region,
+            );
+        }
+    }
+
     pub fn blit(&self, src: &Image, dst: &Image, regions: &[vk::ImageBlit]) {
         unsafe {
             self.context.device.cmd_blit_image(
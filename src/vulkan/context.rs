@@ -5,7 +5,10 @@ use super::{
 use ash::{
     extensions::{
         ext::MetalSurface,
-        khr::{AccelerationStructure, Surface, Win32Surface},
+        khr::{
+            AccelerationStructure, Surface, Swapchain, WaylandSurface, Win32Surface, XcbSurface,
+            XlibSurface,
+        },
     },
     vk,
 };
@@ -37,11 +40,28 @@ pub struct Context {
 impl Context {
     pub fn new(window: &Window) -> Self {
         let entry = unsafe { ash::Entry::load() }.unwrap();
+        let instance = create_instance(&entry, get_window_extensions(window));
 
-        let instance = create_instance(&entry, window);
+        Self::from_instance(entry, instance, &[Swapchain::name().as_ptr()])
+    }
+
+    // Creates a context without any window surface or swapchain support, for rendering on
+    // machines without a display server
+    pub fn headless() -> Self {
+        let entry = unsafe { ash::Entry::load() }.unwrap();
+        let instance = create_instance(&entry, &[]);
+
+        Self::from_instance(entry, instance, &[])
+    }
+
+    fn from_instance(
+        entry: ash::Entry,
+        instance: ash::Instance,
+        device_extensions: &[*const c_char],
+    ) -> Self {
         let physical = pick_physical(&instance);
         let queue_family = get_queue_family(&instance, physical);
-        let device = create_device(&instance, physical, queue_family);
+        let device = create_device(&instance, physical, queue_family, device_extensions);
         let queue = get_queue(&device, queue_family);
 
         let allocator = {
@@ -70,6 +90,11 @@ impl Context {
         }
     }
 
+    pub fn limits(&self) -> vk::PhysicalDeviceLimits {
+        let properties = unsafe { self.instance.get_physical_device_properties(self.physical) };
+        properties.limits
+    }
+
     pub fn submit(
         &self,
         submits: &[CommandList],
@@ -148,11 +173,31 @@ pub fn get_window_extensions(window: &Window) -> &'static [*const c_char] {
             &WINDOWS
         }
 
+        RawDisplayHandle::Wayland(_) => {
+            const WAYLAND: [*const c_char; 2] =
+                [Surface::name().as_ptr(), WaylandSurface::name().as_ptr()];
+
+            &WAYLAND
+        }
+
+        RawDisplayHandle::Xlib(_) => {
+            const XLIB: [*const c_char; 2] =
+                [Surface::name().as_ptr(), XlibSurface::name().as_ptr()];
+
+            &XLIB
+        }
+
+        RawDisplayHandle::Xcb(_) => {
+            const XCB: [*const c_char; 2] = [Surface::name().as_ptr(), XcbSurface::name().as_ptr()];
+
+            &XCB
+        }
+
         _ => panic!("Unsupported platform"),
     }
 }
 
-pub fn create_instance(entry: &ash::Entry, window: &Window) -> ash::Instance {
+pub fn create_instance(entry: &ash::Entry, window_extensions: &[*const c_char]) -> ash::Instance {
     let app_name = CString::new("duckyboo").unwrap();
 
     let app_info = vk::ApplicationInfo::builder()
@@ -163,7 +208,7 @@ pub fn create_instance(entry: &ash::Entry, window: &Window) -> ash::Instance {
         .api_version(vk::API_VERSION_1_3);
 
     let mut extensions = Vec::new();
-    extensions.extend_from_slice(&get_window_extensions(window));
+    extensions.extend_from_slice(window_extensions);
 
     #[allow(unused_mut)]
     let mut flags = vk::InstanceCreateFlags::empty();
@@ -205,14 +250,14 @@ pub fn create_device(
     instance: &ash::Instance,
     physical: vk::PhysicalDevice,
     family: u32,
+    additional_extensions: &[*const c_char],
 ) -> ash::Device {
     let queue_infos = [vk::DeviceQueueCreateInfo::builder()
         .queue_family_index(family)
         .queue_priorities(&[1.0])
         .build()];
 
-    let extensions = [
-        ash::extensions::khr::Swapchain::name().as_ptr(),
+    let mut extensions = vec![
         ash::extensions::khr::AccelerationStructure::name().as_ptr(),
         ash::extensions::khr::DeferredHostOperations::name().as_ptr(),
         vk::KhrRayQueryFn::name().as_ptr(),
@@ -220,6 +265,7 @@ pub fn create_device(
         #[cfg(any(target_os = "macos", target_os = "ios"))]
         vk::KhrPortabilitySubsetFn::name().as_ptr(),
     ];
+    extensions.extend_from_slice(additional_extensions);
 
     let mut features_1_3 = vk::PhysicalDeviceVulkan13Features::builder()
         .dynamic_rendering(true)
@@ -229,6 +275,10 @@ pub fn create_device(
     let mut features_1_2 = vk::PhysicalDeviceVulkan12Features::builder()
         .buffer_device_address(true)
         .buffer_device_address_capture_replay(true)
+        .descriptor_indexing(true)
+        .descriptor_binding_partially_bound(true)
+        .runtime_descriptor_array(true)
+        .shader_sampled_image_array_non_uniform_indexing(true)
         .build();
 
     let mut features_as = vk::PhysicalDeviceAccelerationStructureFeaturesKHR::builder()
@@ -17,6 +17,13 @@ pub struct DescriptorBufferWrite<'a> {
     pub binding: u32,
 }
 
+// Writes consecutive elements of an array binding, starting from the first
+pub struct DescriptorImageArrayWrite<'a> {
+    pub images: &'a [vk::DescriptorImageInfo],
+    pub image_kind: vk::DescriptorType,
+    pub binding: u32,
+}
+
 pub struct DescriptorTLASWrite<'a> {
     pub reference: &'a AccelerationStructure,
     pub binding: u32,
@@ -74,6 +81,26 @@ impl DescriptorSet {
         }
     }
 
+    pub fn write_image_array(&self, array: DescriptorImageArrayWrite) {
+        if array.images.is_empty() {
+            return;
+        }
+
+        let write = vk::WriteDescriptorSet::builder()
+            .descriptor_type(array.image_kind)
+            .dst_array_element(0)
+            .dst_binding(array.binding)
+            .dst_set(self.handle)
+            .image_info(array.images)
+            .build();
+
+        unsafe {
+            self.context
+                .device
+                .update_descriptor_sets(std::slice::from_ref(&write), &[])
+        };
+    }
+
     pub fn write_tlas(&self, tlas: DescriptorTLASWrite) {
         let handles = [tlas.reference.handle];
 
@@ -121,9 +148,15 @@ impl DescriptorPool {
             .descriptor_count(100)
             .build();
 
+        let storage_buffers = vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(100)
+            .build();
+
+        // Large enough for a few sets with a full array of scene textures
         let sampled_images = vk::DescriptorPoolSize::builder()
             .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .descriptor_count(100)
+            .descriptor_count(4096)
             .build();
 
         let tlasses = vk::DescriptorPoolSize::builder()
@@ -131,7 +164,13 @@ impl DescriptorPool {
             .descriptor_count(100)
             .build();
 
-        let sizes = [storage_images, uniform_buffers, sampled_images, tlasses];
+        let sizes = [
+            storage_images,
+            uniform_buffers,
+            storage_buffers,
+            sampled_images,
+            tlasses,
+        ];
 
         let create_info = vk::DescriptorPoolCreateInfo::builder()
             .pool_sizes(&sizes)
@@ -223,7 +262,21 @@ impl DescriptorSetLayout {
             })
             .collect::<Vec<_>>();
 
-        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        // Arrays of descriptors are partially bound, so only the elements in use need writing
+        let flags = bindings
+            .iter()
+            .map(|binding| match binding.descriptor_count {
+                1 => vk::DescriptorBindingFlags::empty(),
+                _ => vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+            })
+            .collect::<Vec<_>>();
+
+        let mut binding_flags =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&flags);
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&bindings)
+            .push_next(&mut binding_flags);
 
         let handle = unsafe {
             context
@@ -4,7 +4,7 @@ use super::{
     sync::Semaphore,
 };
 use ash::{
-    extensions::khr::{Surface, Swapchain, Win32Surface},
+    extensions::khr::{Surface, Swapchain, WaylandSurface, Win32Surface, XcbSurface, XlibSurface},
     vk,
 };
 use glam::UVec2;
@@ -32,6 +32,8 @@ pub struct Display {
 
 impl Display {
     const IMAGE_COUNT: u32 = 3;
+    const PREFERRED_FORMATS: [vk::Format; 2] =
+        [vk::Format::B8G8R8A8_UNORM, vk::Format::R8G8B8A8_UNORM];
 
     pub fn new(context: Arc<Context>, window: &Window) -> Arc<Self> {
         let surface_loader = Surface::new(&context.entry, &context.instance);
@@ -44,12 +46,23 @@ impl Display {
                 .get_physical_device_surface_capabilities(context.physical, surface)
                 .unwrap()
         };
-        let format = unsafe {
+        let formats = unsafe {
             surface_loader
                 .get_physical_device_surface_formats(context.physical, surface)
-                .unwrap()[0]
+                .unwrap()
         };
 
+        // The swapchain is written to from compute shaders, which sRGB formats don't support,
+        // so prefer a UNORM format and do the sRGB encoding by hand
+        let format = formats
+            .iter()
+            .find(|format| {
+                Self::PREFERRED_FORMATS.contains(&format.format)
+                    && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            })
+            .copied()
+            .unwrap_or(formats[0]);
+
         let create_info = vk::SwapchainCreateInfoKHR::builder()
             .surface(surface)
             .min_image_count(Self::IMAGE_COUNT)
@@ -170,6 +183,36 @@ pub fn create_surface(
             unsafe { surface_fn.create_win32_surface(&create_info, None).unwrap() }
         }
 
+        (RawDisplayHandle::Wayland(display), RawWindowHandle::Wayland(window)) => {
+            let create_info = vk::WaylandSurfaceCreateInfoKHR::builder()
+                .display(display.display.as_ptr())
+                .surface(window.surface.as_ptr());
+            let surface_fn = WaylandSurface::new(entry, instance);
+            unsafe {
+                surface_fn
+                    .create_wayland_surface(&create_info, None)
+                    .unwrap()
+            }
+        }
+
+        (RawDisplayHandle::Xlib(display), RawWindowHandle::Xlib(window)) => {
+            let dpy = display.display.unwrap();
+            let create_info = vk::XlibSurfaceCreateInfoKHR::builder()
+                .dpy(dpy.as_ptr().cast())
+                .window(window.window);
+            let surface_fn = XlibSurface::new(entry, instance);
+            unsafe { surface_fn.create_xlib_surface(&create_info, None).unwrap() }
+        }
+
+        (RawDisplayHandle::Xcb(display), RawWindowHandle::Xcb(window)) => {
+            let connection = display.connection.unwrap();
+            let create_info = vk::XcbSurfaceCreateInfoKHR::builder()
+                .connection(connection.as_ptr())
+                .window(window.window.get());
+            let surface_fn = XcbSurface::new(entry, instance);
+            unsafe { surface_fn.create_xcb_surface(&create_info, None).unwrap() }
+        }
+
         #[cfg(target_os = "macos")]
         (RawDisplayHandle::AppKit(_), RawWindowHandle::AppKit(window)) => {
             use raw_window_metal::{appkit, Layer};
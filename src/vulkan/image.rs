@@ -17,6 +17,17 @@ impl Image {
         format: vk::Format,
         usage: vk::ImageUsageFlags,
         debug_name: &str,
+    ) -> Self {
+        Self::with_mip_levels(context, extent, 1, format, usage, debug_name)
+    }
+
+    pub fn with_mip_levels(
+        context: Arc<Context>,
+        extent: glam::UVec3,
+        mip_levels: u32,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        debug_name: &str,
     ) -> Self {
         let image_type = if extent.z > 1 {
             vk::ImageType::TYPE_3D
@@ -32,7 +43,7 @@ impl Image {
                 height: extent.y,
                 depth: extent.z,
             },
-            mip_levels: 1,
+            mip_levels,
             array_layers: 1,
             samples: vk::SampleCountFlags::TYPE_1,
             tiling: vk::ImageTiling::OPTIMAL,
@@ -168,7 +179,11 @@ impl Sampler {
             .min_lod(0.0)
             .max_lod(vk::LOD_CLAMP_NONE);
 
-        let handle = unsafe { context.device.create_sampler(&create_info, None) }.unwrap();
+        Self::from_create_info(context, &create_info)
+    }
+
+    pub fn from_create_info(context: Arc<Context>, create_info: &vk::SamplerCreateInfo) -> Self {
+        let handle = unsafe { context.device.create_sampler(create_info, None) }.unwrap();
 
         Self { context, handle }
     }
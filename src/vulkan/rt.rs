@@ -7,12 +7,14 @@ pub struct GeometryDescription {
     pub indices: vk::DeviceAddress,
     pub max_vertex: u32,
     pub primitives: u32,
+    // Non-opaque geometry is reported to ray queries as candidates, which the shader confirms
+    pub opaque: bool,
 }
 
 struct BlasBuild {
     size_info: vk::AccelerationStructureBuildSizesInfoKHR,
-    geometry: vk::AccelerationStructureGeometryKHR,
-    range: vk::AccelerationStructureBuildRangeInfoKHR,
+    geometries: Vec<vk::AccelerationStructureGeometryKHR>,
+    ranges: Vec<vk::AccelerationStructureBuildRangeInfoKHR>,
 }
 
 pub struct GeometryInstance {
@@ -28,37 +30,56 @@ pub struct AccelerationStructure {
 }
 
 impl AccelerationStructure {
-    pub fn build_bottom_levels(context: Arc<Context>, descs: &[GeometryDescription]) -> Vec<Self> {
+    // Builds one BLAS for each set of geometries, which the shader tells apart by geometry index
+    pub fn build_bottom_levels(
+        context: Arc<Context>,
+        descs: &[Vec<GeometryDescription>],
+    ) -> Vec<Self> {
         let mut builds = Vec::with_capacity(descs.len());
         let mut scratch_size = 0;
 
-        for desc in descs {
-            let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
-                .vertex_format(vk::Format::R32G32B32_SFLOAT)
-                .vertex_data(vk::DeviceOrHostAddressConstKHR {
-                    device_address: desc.vertices,
-                })
-                .vertex_stride((std::mem::size_of::<f32>() * 3) as u64)
-                .index_type(vk::IndexType::UINT32)
-                .index_data(vk::DeviceOrHostAddressConstKHR {
-                    device_address: desc.indices,
-                })
-                .max_vertex(desc.max_vertex);
-
-            let geometry = vk::AccelerationStructureGeometryKHR::builder()
-                .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
-                .flags(vk::GeometryFlagsKHR::OPAQUE)
-                .geometry(vk::AccelerationStructureGeometryDataKHR {
-                    triangles: *triangles,
-                })
-                .build();
-
-            let range = vk::AccelerationStructureBuildRangeInfoKHR::builder()
-                .first_vertex(0)
-                .primitive_count(desc.primitives)
-                .primitive_offset(0)
-                .transform_offset(0)
-                .build();
+        for descs in descs {
+            let mut geometries = Vec::with_capacity(descs.len());
+            let mut ranges = Vec::with_capacity(descs.len());
+
+            for desc in descs {
+                let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
+                    .vertex_format(vk::Format::R32G32B32_SFLOAT)
+                    .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: desc.vertices,
+                    })
+                    .vertex_stride((std::mem::size_of::<f32>() * 3) as u64)
+                    .index_type(vk::IndexType::UINT32)
+                    .index_data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: desc.indices,
+                    })
+                    .max_vertex(desc.max_vertex);
+
+                // Each candidate must only be seen once, or blended geometry would be tested twice
+                let flags = if desc.opaque {
+                    vk::GeometryFlagsKHR::OPAQUE
+                } else {
+                    vk::GeometryFlagsKHR::NO_DUPLICATE_ANY_HIT_INVOCATION
+                };
+
+                let geometry = vk::AccelerationStructureGeometryKHR::builder()
+                    .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+                    .flags(flags)
+                    .geometry(vk::AccelerationStructureGeometryDataKHR {
+                        triangles: *triangles,
+                    })
+                    .build();
+
+                let range = vk::AccelerationStructureBuildRangeInfoKHR::builder()
+                    .first_vertex(0)
+                    .primitive_count(desc.primitives)
+                    .primitive_offset(0)
+                    .transform_offset(0)
+                    .build();
+
+                geometries.push(geometry);
+                ranges.push(range);
+            }
 
             let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
                 .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
@@ -67,23 +88,28 @@ impl AccelerationStructure {
                     vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
                         | vk::BuildAccelerationStructureFlagsKHR::ALLOW_DATA_ACCESS,
                 )
-                .geometries(std::slice::from_ref(&geometry))
+                .geometries(&geometries)
                 .build();
 
+            let primitive_counts = descs
+                .iter()
+                .map(|desc| desc.primitives)
+                .collect::<Vec<u32>>();
+
             let size_info = unsafe {
                 context
                     .acceleration_structures
                     .get_acceleration_structure_build_sizes(
                         vk::AccelerationStructureBuildTypeKHR::HOST,
                         &build_info,
-                        &[desc.primitives],
+                        &primitive_counts,
                     )
             };
 
             let build = BlasBuild {
                 size_info,
-                geometry,
-                range,
+                geometries,
+                ranges,
             };
 
             builds.push(build);
@@ -111,7 +137,7 @@ impl AccelerationStructure {
                     vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
                         | vk::BuildAccelerationStructureFlagsKHR::ALLOW_DATA_ACCESS,
                 )
-                .geometries(std::slice::from_ref(&build.geometry))
+                .geometries(&build.geometries)
                 .build();
 
             let buffer = Buffer::new(
@@ -141,10 +167,7 @@ impl AccelerationStructure {
 
             let cmds = command_pool.allocate();
             cmds.begin();
-            cmds.build_acceleration_structures(
-                std::slice::from_ref(&build_info),
-                &[std::slice::from_ref(&build.range)],
-            );
+            cmds.build_acceleration_structures(std::slice::from_ref(&build_info), &[&build.ranges]);
 
             let barrier = vk::MemoryBarrier2 {
                 src_stage_mask: vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR,
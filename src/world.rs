@@ -1,4 +1,14 @@
-use crate::input::{Input, Inputs};
+use crate::{
+    bvh::{Ray, SceneBvh},
+    input::{Input, Inputs},
+    loader::{
+        cameras::{CameraKind, SceneCamera},
+        SceneData,
+    },
+    surfaces::Surfaces,
+};
+use glam::Vec4Swizzles;
+use std::str::FromStr;
 use winit::keyboard::KeyCode;
 
 pub struct Object {
@@ -7,28 +17,177 @@ pub struct Object {
     pub scale: glam::Vec3A,
 }
 
+#[derive(Clone, Copy, PartialEq)]
 pub struct Camera {
     pub position: glam::Vec3A,
     pub rotation: glam::Quat,
 }
 
+// How rays leave the camera
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    // Parallel rays, covering `height` world units vertically
+    Orthographic {
+        height: f32,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CameraMode {
+    // Move freely, looking around with the mouse
+    #[default]
+    Fly,
+    // Turn around a pivot in front of the camera, which the movement keys pan and dolly towards
+    Orbit,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 2] = [CameraMode::Fly, CameraMode::Orbit];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CameraMode::Fly => "Fly",
+            CameraMode::Orbit => "Orbit",
+        }
+    }
+}
+
+pub struct CameraControls {
+    pub mode: CameraMode,
+
+    // Top speed in units per second, and how quickly it's reached in units per second squared
+    pub speed: f32,
+    pub acceleration: f32,
+
+    // Radians turned for each unit of mouse movement
+    pub sensitivity: f32,
+
+    // Distance from the camera to the pivot it orbits around
+    pub orbit_distance: f32,
+
+    velocity: glam::Vec3A,
+}
+
+impl Default for CameraControls {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::default(),
+            speed: 5.0,
+            acceleration: 20.0,
+            sensitivity: 0.003,
+            orbit_distance: 4.0,
+            velocity: glam::Vec3A::ZERO,
+        }
+    }
+}
+
+// An analytic daylight sky with a sun, used in place of the environment map when enabled
+#[derive(Clone, Copy, PartialEq)]
+pub struct Sky {
+    pub enabled: bool,
+
+    // Angle of the sun above the horizon, and around the y axis from +z, in degrees
+    pub elevation: f32,
+    pub azimuth: f32,
+
+    // Haziness of the atmosphere, from 2 on a very clear day up to 10 in heavy haze
+    pub turbidity: f32,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            elevation: 45.0,
+            azimuth: 0.0,
+            turbidity: 3.0,
+        }
+    }
+}
+
+// The curve used to map HDR radiance into the displayable range
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Tonemap {
+    Reinhard,
+    #[default]
+    Aces,
+    AgX,
+}
+
+impl Tonemap {
+    pub const ALL: [Tonemap; 3] = [Tonemap::Reinhard, Tonemap::Aces, Tonemap::AgX];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tonemap::Reinhard => "Reinhard",
+            Tonemap::Aces => "ACES",
+            Tonemap::AgX => "AgX",
+        }
+    }
+}
+
+impl FromStr for Tonemap {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Tonemap::ALL
+            .into_iter()
+            .find(|tonemap| tonemap.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow::anyhow!("Unknown tonemapper {}", s))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub struct RenderSettings {
+    pub projection: Projection,
     pub fov: f32,
     pub near: f32,
     pub far: f32,
 
+    // Distance from the camera to the plane in focus, and the diameter of the lens, where an
+    // aperture of zero gives a pinhole camera with everything in focus
     pub focal_length: f32,
     pub aperture: f32,
+    // Number of blades shaping the aperture into a polygon, or zero for a circular one
+    pub aperture_blades: u32,
+
     pub exposure: f32,
+    pub tonemap: Tonemap,
+
+    // Scales the environment map's radiance, and rotates it around the y axis in degrees
+    pub environment_intensity: f32,
+    pub environment_rotation: f32,
+    pub sky: Sky,
 
     pub samples: u32,
     pub bounces: u32,
 }
 
+// Progress of the renderer, written back each frame for display in the interface
+#[derive(Default)]
+pub struct RenderStats {
+    pub frames: u32,
+    pub samples: u32,
+}
+
 pub struct World {
     pub camera: Camera,
+    pub controls: CameraControls,
     pub settings: RenderSettings,
+    pub stats: RenderStats,
     pub objects: Vec<Object>,
+
+    // Cameras authored in the loaded scene, which the camera can be snapped to
+    pub cameras: Vec<SceneCamera>,
+
+    // The loaded scene's geometry, for picking what's under the cursor
+    pub bvh: Option<SceneBvh>,
+    pub surfaces: Option<Surfaces>,
+
+    // Set while waiting for a click to choose what to focus on
+    pub picking_focus: bool,
 }
 
 impl World {
@@ -39,67 +198,223 @@ impl World {
         };
 
         let settings = RenderSettings {
+            projection: Projection::default(),
             fov: 60.0,
             near: 0.01,
             far: 100.0,
-            focal_length: 16.0,
-            aperture: 1.0,
+            focal_length: 4.0,
+            aperture: 0.0,
+            aperture_blades: 0,
             exposure: 1.0,
+            tonemap: Tonemap::default(),
+            environment_intensity: 1.0,
+            environment_rotation: 0.0,
+            sky: Sky::default(),
             samples: 8,
             bounces: 3,
         };
 
         Self {
             camera,
+            controls: CameraControls::default(),
             settings,
+            stats: RenderStats::default(),
             objects: Vec::default(),
+            cameras: Vec::default(),
+            bvh: None,
+            surfaces: None,
+            picking_focus: false,
         }
     }
 
-    pub fn update(&mut self, inputs: &Inputs) {
-        const CAM_SPEED: f32 = 0.5;
-        const CAM_SENS: f32 = 0.1;
+    // Takes what the world needs from a newly loaded scene, before it's handed to the renderer
+    pub fn load_scene(&mut self, data: &mut SceneData) {
+        self.cameras = std::mem::take(&mut data.cameras);
+        self.bvh = Some(SceneBvh::new(&data.meshes, &data.instances));
+        self.surfaces = Some(Surfaces::for_alpha_testing(data));
+    }
 
-        let right = self.camera.rotation * glam::vec3a(1.0, 0.0, 0.0);
-        let forward = self.camera.rotation * glam::vec3a(0.0, 0.0, 1.0);
+    pub fn view(&self) -> glam::Mat4 {
+        let forward = self.camera.rotation * glam::vec3(0.0, 0.0, 1.0);
+        let up = self.camera.rotation * glam::vec3(0.0, 1.0, 0.0);
+        glam::Mat4::look_to_lh(self.camera.position.into(), forward, up)
+    }
 
-        for event in inputs.listener.try_iter() {
-            match event {
-                Input::Keyboard(key) => {
-                    if key == KeyCode::KeyW {
-                        self.camera.position += forward * CAM_SPEED
-                    }
-                    if key == KeyCode::KeyS {
-                        self.camera.position -= forward * CAM_SPEED
-                    }
+    pub fn projection(&self, dims: glam::UVec2) -> glam::Mat4 {
+        let aspect = dims.as_vec2();
+        let aspect_ratio = aspect.x / aspect.y;
 
-                    if key == KeyCode::KeyD {
-                        self.camera.position += right * CAM_SPEED
-                    }
-                    if key == KeyCode::KeyA {
-                        self.camera.position -= right * CAM_SPEED
-                    }
+        match self.settings.projection {
+            Projection::Perspective => glam::Mat4::perspective_lh(
+                f32::to_radians(self.settings.fov),
+                aspect_ratio,
+                self.settings.near,
+                self.settings.far,
+            ),
+            Projection::Orthographic { height } => {
+                let half = glam::vec2(height * aspect_ratio, height) * 0.5;
+                glam::Mat4::orthographic_lh(
+                    -half.x,
+                    half.x,
+                    -half.y,
+                    half.y,
+                    self.settings.near,
+                    self.settings.far,
+                )
+            }
+        }
+    }
 
-                    if key == KeyCode::Space {
-                        self.camera.position.y += CAM_SPEED
-                    };
-                    if key == KeyCode::ShiftLeft {
-                        self.camera.position.y -= CAM_SPEED
-                    };
-                }
+    // The ray through the centre of the lens for a point on the image, where `uv` is in the
+    // range [0, 1] from the top left corner. Matches the ray generation in the shader
+    pub fn camera_ray(&self, uv: glam::Vec2, dims: glam::UVec2) -> Ray {
+        let inv_view = self.view().inverse();
+        let inv_proj = self.projection(dims).inverse();
+        let coord = glam::vec2(uv.x, 1.0 - uv.y) * 2.0 - 1.0;
+
+        if let Projection::Orthographic { .. } = self.settings.projection {
+            let near = inv_proj * glam::vec4(coord.x, coord.y, 0.0, 1.0);
+            return Ray {
+                origin: (inv_view * (near / near.w)).xyz().into(),
+                dir: (inv_view * glam::Vec4::Z).xyz().into(),
+            };
+        }
+
+        let target = inv_proj * glam::vec4(coord.x, coord.y, 1.0, 1.0);
+        let dir = (target.xyz() / target.w).normalize().extend(0.0);
+        Ray {
+            origin: self.camera.position,
+            dir: (inv_view * dir).xyz().into(),
+        }
+    }
+
+    // Focuses on whatever is under `uv` on the image, leaving the focus alone if that's nothing
+    pub fn focus_at(&mut self, uv: glam::Vec2, dims: glam::UVec2) {
+        let (Some(bvh), Some(surfaces)) = (&self.bvh, &self.surfaces) else {
+            return;
+        };
+
+        // Cut out parts of surfaces are skipped like in the renderer, but blended surfaces are
+        // treated as solid where they're more than half opaque, so what's picked doesn't flicker
+        let ray = self.camera_ray(uv, dims);
+        let accept = |mesh, hit: &_| surfaces.alpha_test(mesh, hit, || 0.5);
+        if let Some(hit) = bvh.intersect(&ray, 0.0, 10000.0, accept) {
+            // The plane in focus faces the camera, so measure the distance along its axis
+            let forward = self.camera.rotation * glam::Vec3A::Z;
+            self.settings.focal_length = hit.t * ray.dir.dot(forward);
+        }
+    }
+
+    // Moves the camera to the scene camera at `index`, and takes on its projection
+    pub fn use_camera(&mut self, index: usize) {
+        let Some(camera) = self.cameras.get(index) else {
+            return;
+        };
+
+        self.camera = Camera {
+            position: camera.position,
+            rotation: camera.rotation,
+        };
+
+        match camera.kind {
+            CameraKind::Perspective { yfov } => {
+                self.settings.projection = Projection::Perspective;
+                self.settings.fov = yfov.to_degrees();
+            }
+            CameraKind::Orthographic { ymag } => {
+                self.settings.projection = Projection::Orthographic { height: ymag * 2.0 };
+            }
+        }
+
+        self.settings.near = camera.znear;
+        if let Some(zfar) = camera.zfar {
+            self.settings.far = zfar;
+        }
+    }
 
+    // Switches how the camera is controlled. Orbiting starts around the point in focus
+    pub fn set_camera_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.controls.mode != CameraMode::Orbit {
+            self.controls.orbit_distance = self.settings.focal_length.max(0.01);
+        }
+
+        self.controls.mode = mode;
+        self.controls.velocity = glam::Vec3A::ZERO;
+    }
+
+    // Moves the camera from the inputs received since the last update, `dt` seconds ago
+    pub fn update(&mut self, inputs: &mut Inputs, dt: f32) {
+        for event in inputs.drain() {
+            match event {
                 Input::Mouse(delta) => {
                     // Convert the delta from f64s to f32s
-                    let movement = delta.as_vec2() * CAM_SENS;
+                    let movement = delta.as_vec2() * self.controls.sensitivity;
+
+                    // Yaw around the world's up axis, and pitch around the camera's own x axis,
+                    // keeping the pivot in the same place when orbiting
+                    let pivot = self.orbit_pivot();
 
                     let pitch = glam::Quat::from_rotation_x(-movement.y);
                     let yaw = glam::Quat::from_rotation_y(movement.x);
+                    self.camera.rotation = (yaw * self.camera.rotation * pitch).normalize();
 
-                    self.camera.rotation = pitch * self.camera.rotation * yaw;
+                    if self.controls.mode == CameraMode::Orbit {
+                        let forward = self.camera.rotation * glam::Vec3A::Z;
+                        self.camera.position = pivot - forward * self.controls.orbit_distance;
+                    }
                 }
 
-                Input::Unknown => (),
+                Input::Click { position, size } => {
+                    if self.picking_focus {
+                        self.focus_at(position.as_vec2() / size.as_vec2(), size);
+                        self.picking_focus = false;
+                    }
+                }
+
+                Input::Keyboard { .. } | Input::FocusLost | Input::Unknown => (),
+            }
+        }
+
+        // The direction the held keys ask to move in, in the camera's right, up and forward axes
+        let axis = |positive: KeyCode, negative: KeyCode| {
+            inputs.is_held(positive) as i32 as f32 - inputs.is_held(negative) as i32 as f32
+        };
+        let wish = glam::vec3a(
+            axis(KeyCode::KeyD, KeyCode::KeyA),
+            axis(KeyCode::Space, KeyCode::ShiftLeft),
+            axis(KeyCode::KeyW, KeyCode::KeyS),
+        );
+
+        // Accelerate towards the top speed in that direction, or towards a stop if nothing is held
+        let target = wish.normalize_or_zero() * self.controls.speed;
+        let change =
+            (target - self.controls.velocity).clamp_length_max(self.controls.acceleration * dt);
+        self.controls.velocity += change;
+
+        let step = self.controls.velocity * dt;
+        let right = self.camera.rotation * glam::Vec3A::X;
+        let forward = self.camera.rotation * glam::Vec3A::Z;
+
+        match self.controls.mode {
+            CameraMode::Fly => {
+                self.camera.position += right * step.x + forward * step.z;
+                self.camera.position.y += step.y;
+            }
+
+            // Moving forwards dollies in towards the pivot, everything else pans it around
+            CameraMode::Orbit => {
+                let distance = (self.controls.orbit_distance - step.z).max(0.01);
+                self.camera.position += forward * (self.controls.orbit_distance - distance);
+                self.camera.position += right * step.x;
+                self.camera.position.y += step.y;
+                self.controls.orbit_distance = distance;
             }
         }
     }
+
+    // The point the camera orbits around, in front of it
+    fn orbit_pivot(&self) -> glam::Vec3A {
+        let forward = self.camera.rotation * glam::Vec3A::Z;
+        self.camera.position + forward * self.controls.orbit_distance
+    }
 }